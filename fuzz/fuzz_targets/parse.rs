@@ -0,0 +1,20 @@
+#![no_main]
+use asf::options::ParseOptions;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let options = [
+        ParseOptions::default(),
+        ParseOptions {
+            recover: true,
+            lenient: true,
+            ..Default::default()
+        },
+    ];
+    for options in options.iter() {
+        if let Ok(container) = asf::parse_with_options(data, options) {
+            let mut out = Vec::new();
+            let _ = container.write(&mut out);
+        }
+    }
+});
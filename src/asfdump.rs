@@ -1,11 +1,129 @@
-use asf::parse;
-use std::{env::args, fs::File, io::Read};
+//! Prints the parsed structure of ASF files.
+//!
+//! Exits with status 0 if every file parsed, 1 if any file failed to parse
+//! and 2 if a file could not be read. Parse failures are reported on stderr
+//! with the offset, the enclosing objects and a hexdump of the bad bytes.
+
+use asf::{error::AsfError, parse};
+use std::{env::args, process::exit};
+
+const ROW_LEN: usize = 16;
+const CONTEXT_ROWS: usize = 2;
 
 fn main() {
+    let mut status = 0;
     for name in args().skip(1) {
-        let mut buffer = Vec::new();
-        let mut f = File::open(name).expect("opening file failed");
-        f.read_to_end(&mut buffer).expect("reading file failed");
-        println!("{:?}", parse(&buffer).expect("parsing file failed"));
+        let buffer = match load(&name) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                eprintln!("{}: {}", name, error);
+                exit(2);
+            }
+        };
+        match parse(&buffer) {
+            Ok(container) => println!("{:?}", container),
+            Err(error) => {
+                eprint!("{}", report(&name, &buffer, &error));
+                status = 1;
+            }
+        }
+    }
+    exit(status);
+}
+
+#[cfg(feature = "mmap")]
+fn load(name: &str) -> std::io::Result<asf::mmap::MappedFile> {
+    asf::open_mmap(name)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn load(name: &str) -> std::io::Result<Vec<u8>> {
+    std::fs::read(name)
+}
+
+fn report(name: &str, buffer: &[u8], error: &AsfError) -> String {
+    let mut out = format!("{}: parsing failed: {}\n", name, error);
+    out += &format!("  offset:   {:#x} ({})\n", error.offset(), error.offset());
+    if !error.path().is_empty() {
+        let path: Vec<String> = error.path().iter().map(|e| e.to_string()).collect();
+        out += &format!("  path:     {}\n", path.join(" > "));
+    }
+    match error {
+        AsfError::BadGuid {
+            expected, found, ..
+        } => {
+            if let Some(expected) = expected {
+                out += &format!("  expected: {}\n", expected);
+            }
+            if let Some(found) = found {
+                out += &format!("  found:    {}\n", found);
+            }
+        }
+        AsfError::SizeMismatch {
+            expected: Some(expected),
+            actual: Some(actual),
+            ..
+        } => {
+            out += &format!("  expected: at least {}\n", expected);
+            out += &format!("  actual:   {}\n", actual);
+        }
+        AsfError::Truncated { .. } => {
+            out += &format!("  file:     {} bytes\n", buffer.len());
+        }
+        _ => {}
+    }
+    out += "\n";
+    out += &hexdump(buffer, error.offset(), bad_len(error));
+    out
+}
+
+/// How many bytes at the error offset to highlight.
+fn bad_len(error: &AsfError) -> usize {
+    match error {
+        AsfError::BadGuid { .. } => 16,
+        AsfError::SizeMismatch { .. } => 8,
+        AsfError::Truncated { .. } => 0,
+        AsfError::InvalidValue { .. } | AsfError::LimitExceeded { .. } => 1,
+    }
+}
+
+/// Dumps the rows around `offset`, with carets under the `len` bytes
+/// starting there.
+fn hexdump(buffer: &[u8], offset: usize, len: usize) -> String {
+    let bad = offset..(offset + len).min(buffer.len());
+    let first = (offset / ROW_LEN).saturating_sub(CONTEXT_ROWS);
+    let last = (offset + len.max(1) - 1) / ROW_LEN + CONTEXT_ROWS;
+    let mut out = String::new();
+    for row in first..=last {
+        let start = row * ROW_LEN;
+        if start >= buffer.len() {
+            break;
+        }
+        let bytes = &buffer[start..(start + ROW_LEN).min(buffer.len())];
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect();
+        out += &format!(
+            "  {:08x}  {:<w$}  |{}|\n",
+            start,
+            hex.join(" "),
+            ascii,
+            w = ROW_LEN * 3 - 1
+        );
+        let marks: String = (start..start + bytes.len())
+            .map(|i| if bad.contains(&i) { "^^ " } else { "   " })
+            .collect();
+        if marks.contains('^') {
+            out += &format!("            {}\n", marks.trim_end());
+        }
+    }
+    if offset >= buffer.len() {
+        out += &format!("  {:08x}  <end of file>\n", buffer.len());
     }
+    out
 }
@@ -0,0 +1,51 @@
+//! Turns a live capture into a seekable file.
+//!
+//! Exits with status 0 if the output was written, 1 if the capture failed to
+//! parse or finalize and 2 if a file could not be read or written.
+
+use asf::{finalize::finalize, parse};
+use std::{
+    env::args,
+    fs::File,
+    io::{BufWriter, Write},
+    process::exit,
+};
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    if args.len() != 2 {
+        eprintln!("usage: asffinalize <live capture> <output>");
+        exit(2);
+    }
+    let (input, output) = (&args[0], &args[1]);
+
+    let buffer = match std::fs::read(input) {
+        Ok(buffer) => buffer,
+        Err(error) => {
+            eprintln!("{}: {}", input, error);
+            exit(2);
+        }
+    };
+    let mut container = match parse(&buffer) {
+        Ok(container) => container,
+        Err(error) => {
+            eprintln!("{}: parsing failed: {}", input, error);
+            exit(1);
+        }
+    };
+    if let Err(error) = finalize(&mut container) {
+        eprintln!("{}: finalizing failed: {}", input, error);
+        exit(1);
+    }
+    if let Err(error) = write(&container, output) {
+        eprintln!("{}: {}", output, error);
+        exit(2);
+    }
+}
+
+fn write(container: &asf::Container, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = BufWriter::new(File::create(name)?);
+    container.write(&mut out)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,336 @@
+//! Reading files from tokio's async I/O, a piece at a time through the push
+//! parser, either pulled from a reader or framed by a codec.
+
+use crate::{
+    data::DataObject,
+    error::AsfError,
+    index::IndexObjects,
+    options::ParseOptions,
+    push::{Event, PushParser},
+    Container,
+};
+use bytes::BytesMut;
+use std::{convert::TryInto, io};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, SeekFrom};
+use tokio_util::codec::Decoder;
+
+/// Reads a file from an async source through a `PushParser`, so only the
+/// object or packet being read is held in memory. Reads are at most 64 KiB.
+pub struct AsyncReader<R> {
+    reader: R,
+    parser: PushParser,
+    chunk: Vec<u8>,
+    /// An event read while skipping packets, returned next.
+    peeked: Option<Event>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, &ParseOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: &ParseOptions) -> Self {
+        AsyncReader {
+            reader,
+            parser: PushParser::new(options),
+            chunk: Vec::new(),
+            peeked: None,
+        }
+    }
+
+    /// Returns the next event, reading from the source as needed, or `None`
+    /// once `Event::End` has been returned.
+    pub async fn next_event(&mut self) -> io::Result<Option<Event>> {
+        if let Some(event) = self.peeked.take() {
+            return Ok(Some(event));
+        }
+        loop {
+            if let Some(event) = self.parser.next_event().map_err(invalid_data)? {
+                return Ok(Some(event));
+            }
+            if self.parser.needed() == Some(0) {
+                return Ok(None);
+            }
+            self.chunk.resize(self.parser.read_len(), 0);
+            let len = self.reader.read(&mut self.chunk).await?;
+            if len == 0 {
+                self.parser.end();
+            }
+            self.parser.push(&self.chunk[..len]);
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncReader<R> {
+    /// Skips the rest of the data object's packets, so the next event is an
+    /// index object or the end. The packets are seeked past when the data
+    /// object's size is known, and read and dropped in a broadcast.
+    pub async fn skip_packets(&mut self) -> io::Result<()> {
+        if let Some(rest) = self.parser.skip_packets() {
+            if rest > 0 {
+                let rest = rest
+                    .try_into()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "data too large"))?;
+                self.reader.seek(SeekFrom::Current(rest)).await?;
+            }
+            return Ok(());
+        }
+        while let Some(event) = self.next_event().await? {
+            if !matches!(event, Event::Packet(_)) {
+                self.peeked = Some(event);
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a whole file from `reader`, starting at its current position.
+pub async fn read_async<R: AsyncRead + Unpin>(reader: R) -> io::Result<Container<'static>> {
+    read_async_with_options(reader, &ParseOptions::default()).await
+}
+
+/// Reads a whole file from `reader` with an `AsyncReader`, collecting its
+/// events into a container. Parse errors are `io::ErrorKind::InvalidData`
+/// errors wrapping the `AsfError`.
+pub async fn read_async_with_options<R: AsyncRead + Unpin>(
+    reader: R,
+    options: &ParseOptions,
+) -> io::Result<Container<'static>> {
+    let mut reader = AsyncReader::with_options(reader, options);
+    let header = match reader.next_event().await? {
+        Some(Event::Header(header)) => header,
+        _ => unreachable!("the header is always the first event"),
+    };
+    let mut data = match reader.next_event().await? {
+        Some(Event::Data {
+            file_id,
+            total_data_packets,
+            reserved,
+        }) => DataObject {
+            file_id,
+            total_data_packets,
+            reserved,
+            packets: Vec::new(),
+            damaged: Vec::new(),
+            truncation: None,
+        },
+        _ => unreachable!("the data object always follows the header"),
+    };
+    let mut indices = IndexObjects::default();
+    // The parser only holds one packet at a time, so the packet limit is
+    // checked here.
+    let max_packets = options.limits.max_packets;
+    let mut offset = reader.parser.offset();
+    while let Some(event) = reader.next_event().await? {
+        match event {
+            Event::Packet(_) if data.packets.len() >= max_packets => {
+                return Err(invalid_data(AsfError::LimitExceeded {
+                    offset,
+                    path: Vec::new(),
+                }));
+            }
+            Event::Packet(packet) => data.packets.push(packet),
+            Event::Index(index) => indices.objects.push(index),
+            Event::End => break,
+            Event::Header(_) | Event::Data { .. } => {
+                unreachable!("the header and data object come first")
+            }
+        }
+        offset = reader.parser.offset();
+    }
+    Ok(Container {
+        header,
+        data,
+        indices,
+    })
+}
+
+fn invalid_data(error: AsfError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Frames a byte stream into the header, the packets and the index objects,
+/// using a `PushParser`. Damaged input ends the stream with an
+/// `io::ErrorKind::InvalidData` error, whose inner error is the `AsfError`.
+#[derive(Debug, Default)]
+pub struct AsfDecoder {
+    parser: PushParser,
+}
+
+impl AsfDecoder {
+    pub fn new(options: &ParseOptions) -> Self {
+        AsfDecoder {
+            parser: PushParser::new(options),
+        }
+    }
+
+    fn next(&mut self) -> io::Result<Option<Event>> {
+        match self.parser.next_event() {
+            Ok(Some(Event::End)) => Ok(None),
+            Ok(event) => Ok(event),
+            Err(error) => Err(invalid_data(error)),
+        }
+    }
+}
+
+impl Decoder for AsfDecoder {
+    type Item = Event;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Event>> {
+        self.parser.push(src);
+        src.clear();
+        self.next()
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<Event>> {
+        self.parser.push(src);
+        src.clear();
+        self.parser.end();
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        guid::DATA_OBJECT,
+        header::file_properties::BROADCAST_FLAG,
+        options::Limits,
+        test_support::{find_guid, variable_size_wmv},
+    };
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[tokio::test]
+    async fn read_async_file() {
+        let container = read_async(BASIC_WMV).await.expect("to read the file");
+        assert_eq!(container, crate::parse(BASIC_WMV).unwrap());
+
+        let (buf, _) = variable_size_wmv();
+        let container = read_async(&buf[..]).await.expect("to read the file");
+        assert_eq!(container, crate::parse(&buf).unwrap());
+
+        let options = ParseOptions {
+            limits: Limits {
+                max_packets: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = read_async_with_options(BASIC_WMV, &options)
+            .await
+            .expect_err("expected the packet limit to fail");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = error
+            .into_inner()
+            .and_then(|error| error.downcast::<AsfError>().ok())
+            .expect("an AsfError");
+        let packets = find_guid(BASIC_WMV, DATA_OBJECT) + 50;
+        assert_eq!(
+            *error,
+            AsfError::LimitExceeded {
+                offset: packets + 1289 * 10,
+                path: Vec::new(),
+            }
+        );
+    }
+
+    async fn indices_after_skipping(buf: &[u8]) -> Vec<Event> {
+        let mut reader = AsyncReader::new(io::Cursor::new(buf));
+        assert!(matches!(
+            reader.next_event().await,
+            Ok(Some(Event::Header(_)))
+        ));
+        assert!(matches!(
+            reader.next_event().await,
+            Ok(Some(Event::Data { .. }))
+        ));
+        reader.skip_packets().await.expect("to skip the packets");
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().await.expect("to read the indices") {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn skip_packets() {
+        let container = crate::parse(BASIC_WMV).unwrap();
+        let mut expected: Vec<_> = container
+            .indices
+            .into_owned()
+            .objects
+            .into_iter()
+            .map(Event::Index)
+            .collect();
+        expected.push(Event::End);
+
+        // The packets are seeked past, so damage in them goes unnoticed.
+        let packets = find_guid(BASIC_WMV, DATA_OBJECT) + 50;
+        let mut buf = BASIC_WMV.to_vec();
+        buf[packets..packets + 1289 * 138].fill(0xff);
+        assert_eq!(indices_after_skipping(&buf).await, expected);
+
+        // A broadcast's packets have to be read through.
+        let mut buf = BASIC_WMV.to_vec();
+        let props = find_guid(&buf, crate::guid::FILE_PROPERTIES_OBJECT) + 24;
+        buf[props + 64] |= BROADCAST_FLAG as u8;
+        assert_eq!(indices_after_skipping(&buf).await, expected);
+    }
+
+    #[test]
+    fn decode_chunks() {
+        let mut decoder = AsfDecoder::default();
+        let mut events = Vec::new();
+        for chunk in BASIC_WMV.chunks(1000) {
+            let mut src = BytesMut::from(chunk);
+            while let Some(event) = decoder.decode(&mut src).expect("to decode") {
+                events.push(event);
+            }
+        }
+        while let Some(event) = decoder.decode_eof(&mut BytesMut::new()).expect("to decode") {
+            events.push(event);
+        }
+
+        let container = crate::parse(BASIC_WMV).unwrap();
+        assert!(matches!(events[0], Event::Header(_)));
+        let packets = events
+            .iter()
+            .filter(|event| matches!(event, Event::Packet(_)))
+            .count();
+        assert_eq!(packets, container.data.packets.len());
+    }
+
+    #[test]
+    fn decode_damaged_packet() {
+        // Overwrite the header of the third packet.
+        let packet = find_guid(BASIC_WMV, DATA_OBJECT) + 50 + 1289 * 2;
+        let mut buf = BASIC_WMV.to_vec();
+        buf[packet..packet + 16].fill(0xff);
+
+        // The error comes before the end of the input.
+        let mut decoder = AsfDecoder::default();
+        let error = buf
+            .chunks(1000)
+            .find_map(|chunk| {
+                let mut src = BytesMut::from(chunk);
+                loop {
+                    match decoder.decode(&mut src) {
+                        Ok(Some(_)) => {}
+                        Ok(None) => return None,
+                        Err(error) => return Some(error),
+                    }
+                }
+            })
+            .expect("the damaged packet to fail");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = error
+            .into_inner()
+            .and_then(|error| error.downcast::<AsfError>().ok())
+            .expect("an AsfError");
+        assert_eq!(error.offset(), packet + 3 + 2);
+    }
+}
@@ -1,8 +1,10 @@
+use crate::{error::ParseError, guid::guid, span::Span};
 use nom::{
-    bytes::streaming::take, error::ParseError, IResult, InputIter, InputLength, InputTake, Parser,
-    ToUsize,
+    bytes::streaming::take, error::ErrorKind, Err, IResult, InputIter, InputLength, InputTake,
+    Parser, ToUsize,
 };
-use nom_locate::LocatedSpan;
+use std::borrow::Cow;
+use uuid::Uuid;
 
 pub fn length_take<I, N, E, F>(mut f: F) -> impl FnMut(I) -> IResult<I, I, E>
 where
@@ -17,15 +19,63 @@ where
     }
 }
 
-pub fn span_bytes<'a, E, F>(
-    mut f: F,
-) -> impl FnMut(LocatedSpan<&'a [u8]>) -> IResult<LocatedSpan<&'a [u8]>, &'a [u8], E>
+/// Like `nom::multi::count`, but never reserves more elements than there are
+/// bytes of input, so a bogus count from the file can't exhaust memory.
+pub fn count<I, O, E, F>(mut f: F, count: usize) -> impl FnMut(I) -> IResult<I, Vec<O>, E>
 where
-    E: ParseError<LocatedSpan<&'a [u8]>>,
-    F: Parser<LocatedSpan<&'a [u8]>, LocatedSpan<&'a [u8]>, E>,
+    I: Clone + InputLength,
+    F: Parser<I, O, E>,
+    E: ParseError<I>,
+{
+    move |i: I| {
+        let mut input = i.clone();
+        let mut res = Vec::with_capacity(count.min(i.input_len()));
+        for _ in 0..count {
+            let len = input.input_len();
+            match f.parse(input.clone()) {
+                Ok((rest, o)) => {
+                    // A parser that consumes nothing would spin for the
+                    // whole count.
+                    if rest.input_len() == len {
+                        return Err(Err::Error(E::from_error_kind(input, ErrorKind::Count)));
+                    }
+                    res.push(o);
+                    input = rest;
+                }
+                Err(Err::Error(e)) => {
+                    return Err(Err::Error(E::add_entry(input, "record", res.len(), e)))
+                }
+                Err(Err::Failure(e)) => {
+                    return Err(Err::Failure(E::add_entry(input, "record", res.len(), e)))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((input, res))
+    }
+}
+
+/// Matches a specific GUID, such as the one starting a top-level object.
+pub fn guid_tag<'a, E: ParseError<Span<'a>>>(
+    expected: Uuid,
+) -> impl Fn(Span<'a>) -> IResult<Span<'a>, Uuid, E> {
+    move |input: Span<'a>| {
+        let (rest, found) = guid(input)?;
+        if found != expected {
+            return Err(Err::Error(E::from_bad_guid(input, expected)));
+        }
+        Ok((rest, found))
+    }
+}
+
+/// Borrows the bytes matched by `f`, without their location.
+pub fn span_bytes<'a, E, F>(mut f: F) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Cow<'a, [u8]>, E>
+where
+    E: ParseError<Span<'a>>,
+    F: Parser<Span<'a>, Span<'a>, E>,
 {
-    move |i: LocatedSpan<&[u8]>| {
+    move |i: Span<'a>| {
         let (i, o1) = f.parse(i)?;
-        Ok((i, o1.fragment()))
+        Ok((i, Cow::Borrowed(*o1.fragment())))
     }
 }
@@ -1,34 +1,350 @@
+pub mod packetize;
 pub mod packets;
+#[cfg(feature = "rayon")]
+mod parallel;
 
-use crate::{error::Error, guid::*, span::Span};
-use nom::error::context;
+use crate::{
+    combinators::guid_tag,
+    error::{AsfError, ParseError},
+    guid::*,
+    header::file_properties::FilePropertiesData,
+    options::{Budget, ParseOptions},
+    span::Span,
+};
+use nom::error::{context, ErrorKind};
 use nom::{
-    bytes::streaming::{tag, take},
-    multi::count,
+    bytes::streaming::take,
     number::streaming::{le_u16, le_u64},
-    IResult,
+    Err, IResult, Needed, Slice,
 };
 use packets::DataPacket;
+use std::{convert::TryInto, io::Write};
 use uuid::Uuid;
 
+/// GUIDs of the top-level objects that may follow the data object. When
+/// reading a broadcast data object, any of these marks the end of the packets.
+const TOP_LEVEL_OBJECTS: [Uuid; 6] = [
+    HEADER_OBJECT,
+    DATA_OBJECT,
+    SIMPLE_INDEX_OBJECT,
+    INDEX_OBJECT,
+    MEDIA_OBJECT_INDEX_OBJECT,
+    TIMECODE_INDEX_OBJECT,
+];
+
+/// A byte range of the data object that could not be parsed as packets, and
+/// the error from the first packet that failed there. Offsets are absolute.
+#[derive(Debug, PartialEq)]
+pub struct DamagedRange {
+    pub start: usize,
+    pub end: usize,
+    pub error: AsfError,
+}
+
+/// Marks a data object that was cut off before all of its packets. Any
+/// partial packet at the end is dropped.
+#[derive(Debug, PartialEq)]
+pub struct Truncation {
+    pub missing_packets: u64,
+    pub missing_bytes: u64,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct DataObject<'a> {
-    file_id: Uuid,
-    total_data_packets: u64,
-    reserved: u16,
-    packets: Vec<DataPacket<'a>>,
+    pub file_id: Uuid,
+    pub total_data_packets: u64,
+    pub reserved: u16,
+    pub packets: Vec<DataPacket<'a>>,
+    pub damaged: Vec<DamagedRange>,
+    pub truncation: Option<Truncation>,
 }
 
 impl<'a> DataObject<'a> {
-    pub fn parse(input: Span<'a>) -> IResult<Span<'a>, DataObject, Error<Span<'a>>> {
-        context("DataObject", move |input: Span<'a>| {
-            let (input, _data_object_guid) = tag(DATA_OBJECT.as_bytes_ms())(input)?;
+    pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, DataObject<'a>, E> {
+        Self::parse_strict(input, &Budget::default())
+    }
+
+    /// Parses a data object written by a live broadcast, where the object size
+    /// and packet count are placeholders. Packets are read until the input
+    /// ends or another top-level object begins.
+    pub fn parse_broadcast<E: ParseError<Span<'a>>>(
+        packet_len: u32,
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, DataObject<'a>, E> {
+        move |input: Span<'a>| Self::parse_broadcast_with(input, packet_len, &Budget::default())
+    }
+
+    /// Parses a data object, skipping over damaged packets. When packets have
+    /// a fixed size, parsing resumes at the next packet boundary. Otherwise,
+    /// the following bytes are scanned for a plausible packet header.
+    pub fn parse_recovering<E: ParseError<Span<'a>>>(
+        broadcast: bool,
+        packet_len: u32,
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, DataObject<'a>, E> {
+        move |input: Span<'a>| {
+            Self::parse_recovering_with(input, broadcast, packet_len, &Budget::default())
+        }
+    }
+
+    /// Parses a data object using the file properties from the header, which
+    /// decide whether it is a broadcast and how large packets are.
+    pub fn parser<E: ParseError<Span<'a>>>(
+        props: Option<&FilePropertiesData>,
+        options: &ParseOptions,
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, DataObject<'a>, E> {
+        let props = props.cloned();
+        let options = *options;
+        move |input: Span<'a>| {
+            let budget = Budget::new(options.limits);
+            Self::parse_with(input, props.as_ref(), &options, &budget)
+        }
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        props: Option<&FilePropertiesData>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, DataObject<'a>, E> {
+        let broadcast = props.is_some_and(|props| props.is_broadcast());
+        let packet_len = props.map(|props| props.maximum_data_packet_size);
+        match (options.recover, broadcast, packet_len) {
+            (false, true, Some(packet_len)) => {
+                Self::parse_broadcast_with(input, packet_len, budget)
+            }
+            (false, _, _) => Self::parse_strict(input, budget),
+            (true, _, _) => {
+                Self::parse_recovering_with(input, broadcast, packet_len.unwrap_or(0), budget)
+            }
+        }
+    }
+
+    fn parse_strict<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, DataObject<'a>, E> {
+        context("data", move |input: Span<'a>| {
+            let (input, _data_object_guid) = guid_tag(DATA_OBJECT)(input)?;
+            let size_input = input;
+            let (input, size) = le_u64(input)?;
+            let (input, file_id) = guid(input)?;
+            let (input, total_data_packets) = le_u64(input)?;
+            let (input, reserved) = le_u16(input)?;
+            let total_packet_len = size
+                .checked_sub(50)
+                .ok_or_else(|| Err::Error(E::from_size_mismatch(size_input, 50, size)))?;
+            let fixed_packet_len = total_packet_len
+                .checked_div(total_data_packets)
+                .unwrap_or(0);
+            let packet_parser =
+                move |input| DataPacket::parse_with(input, fixed_packet_len, budget);
+
+            budget.reserve::<DataPacket, _>(
+                input,
+                total_data_packets.try_into().unwrap_or(usize::MAX),
+                budget.limits.max_packets,
+            )?;
+
+            let (input, data) = match take(total_packet_len)(input) {
+                Ok((input, data)) => (input, data),
+                Err(Err::Incomplete(_)) => {
+                    // The file was cut off inside the data object. Keep every
+                    // complete packet and note how much is missing.
+                    let mut packets = Vec::new();
+                    let mut data = input;
+                    while (packets.len() as u64) < total_data_packets {
+                        match packet_parser(data) {
+                            Ok((rest, packet)) => {
+                                packets.push(packet);
+                                data = rest;
+                            }
+                            Err(Err::Incomplete(_)) => break,
+                            Err(error) => return Err(in_packet(data, packets.len(), error)),
+                        }
+                    }
+                    let truncation = Truncation {
+                        missing_packets: total_data_packets - packets.len() as u64,
+                        missing_bytes: total_packet_len - input.len() as u64,
+                    };
+                    return Ok((
+                        input.slice(input.len()..),
+                        DataObject {
+                            file_id,
+                            total_data_packets,
+                            reserved,
+                            packets,
+                            damaged: Vec::new(),
+                            truncation: Some(truncation),
+                        },
+                    ));
+                }
+                Err(error) => return Err(error),
+            };
+
+            #[cfg(feature = "rayon")]
+            if let Some(packets) =
+                parallel::parse_packets(data, fixed_packet_len, total_data_packets, budget)
+            {
+                return Ok((
+                    input,
+                    DataObject {
+                        file_id,
+                        total_data_packets,
+                        reserved,
+                        packets,
+                        damaged: Vec::new(),
+                        truncation: None,
+                    },
+                ));
+            }
+
+            // The count is untrusted, so reserve no more than the data could
+            // hold.
+            let capacity = total_data_packets
+                .try_into()
+                .unwrap_or(usize::MAX)
+                .min(data.len() / DataPacket::MIN_SIZE);
+            let mut packets = Vec::with_capacity(capacity);
+            let mut data = data;
+            while (packets.len() as u64) < total_data_packets {
+                let (rest, packet) =
+                    packet_parser(data).map_err(|error| in_packet(data, packets.len(), error))?;
+                packets.push(packet);
+                data = rest;
+            }
+
+            Ok((
+                input,
+                DataObject {
+                    file_id,
+                    total_data_packets,
+                    reserved,
+                    packets,
+                    damaged: Vec::new(),
+                    truncation: None,
+                },
+            ))
+        })(input)
+    }
+
+    fn parse_broadcast_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        packet_len: u32,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, DataObject<'a>, E> {
+        context("data", move |input: Span<'a>| {
+            let (input, _data_object_guid) = guid_tag(DATA_OBJECT)(input)?;
+            let (input, _size) = le_u64(input)?;
+            let (input, file_id) = guid(input)?;
+            let (input, total_data_packets) = le_u64(input)?;
+            let (mut input, reserved) = le_u16(input)?;
+            let mut packets = Vec::new();
+            let packet_parser =
+                move |input| DataPacket::parse_with(input, packet_len.into(), budget);
+            let mut truncation = None;
+            while !input.is_empty() && !at_top_level_object(input) {
+                let max = budget.limits.max_packets.saturating_sub(packets.len());
+                budget.reserve::<DataPacket, _>(input, 1, max)?;
+                match packet_parser(input) {
+                    Ok((rest, packet)) => {
+                        packets.push(packet);
+                        input = rest;
+                    }
+                    Err(Err::Incomplete(_)) => {
+                        truncation = Some(Truncation {
+                            missing_packets: 0,
+                            missing_bytes: 0,
+                        });
+                        input = input.slice(input.len()..);
+                    }
+                    Err(error) => return Err(in_packet(input, packets.len(), error)),
+                }
+            }
+
+            Ok((
+                input,
+                DataObject {
+                    file_id,
+                    total_data_packets,
+                    reserved,
+                    packets,
+                    damaged: Vec::new(),
+                    truncation,
+                },
+            ))
+        })(input)
+    }
+
+    fn parse_recovering_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        broadcast: bool,
+        packet_len: u32,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, DataObject<'a>, E> {
+        context("data", move |input: Span<'a>| {
+            let (input, _data_object_guid) = guid_tag(DATA_OBJECT)(input)?;
             let (input, size) = le_u64(input)?;
             let (input, file_id) = guid(input)?;
             let (input, total_data_packets) = le_u64(input)?;
             let (input, reserved) = le_u16(input)?;
-            let total_packet_len = size - 50;
-            let (input, data) = take(total_packet_len)(input)?;
+            let total_packet_len = size.checked_sub(50);
+            let (input, mut data, bounded) = match total_packet_len {
+                Some(total_packet_len) if !broadcast && total_packet_len <= input.len() as u64 => {
+                    let (input, data) = take(total_packet_len)(input)?;
+                    (input, data, true)
+                }
+                _ => (input.slice(input.len()..), input, false),
+            };
+            let missing_bytes = match (broadcast, total_packet_len) {
+                (false, Some(total_packet_len)) => total_packet_len.checked_sub(data.len() as u64),
+                _ => None,
+            };
+
+            let packet_parser =
+                move |input| DataPacket::parse_with(input, packet_len.into(), budget);
+            let mut packets = Vec::new();
+            let mut damaged: Vec<DamagedRange> = Vec::new();
+            let mut truncation = None;
+            while !data.is_empty() && !at_top_level_object(data) {
+                let start = data.location_offset();
+                let max = budget.limits.max_packets.saturating_sub(packets.len());
+                budget.reserve::<DataPacket, _>(data, 1, max)?;
+                match packet_parser(data) {
+                    Ok((rest, packet)) => {
+                        packets.push(packet);
+                        data = rest;
+                    }
+                    Err(Err::Incomplete(needed))
+                        if !bounded && is_cut_off(data, needed, packet_len, missing_bytes) =>
+                    {
+                        // The last packet was cut off along with the file.
+                        truncation = Some(Truncation {
+                            missing_packets: total_data_packets
+                                .saturating_sub(packets.len() as u64),
+                            missing_bytes: missing_bytes.unwrap_or(0),
+                        });
+                        data = data.slice(data.len()..);
+                    }
+                    Err(Err::Failure(error)) => {
+                        return Err(in_packet(data, packets.len(), Err::Failure(error)))
+                    }
+                    Err(_) => {
+                        let skip = next_packet(data, packet_len as usize);
+                        match damaged.last_mut() {
+                            Some(range) if range.end == start => range.end += skip,
+                            _ => damaged.push(DamagedRange {
+                                start,
+                                end: start + skip,
+                                error: diagnose(data, packet_len, budget),
+                            }),
+                        }
+                        data = data.slice(skip..);
+                    }
+                }
+            }
+
+            // Without a usable object size, whatever follows the packets
+            // belongs to the next object.
+            let input = if bounded { input } else { data };
 
             Ok((
                 input,
@@ -36,13 +352,245 @@ impl<'a> DataObject<'a> {
                     file_id,
                     total_data_packets,
                     reserved,
-                    packets: count(
-                        DataPacket::parser(total_data_packets, total_packet_len),
-                        total_data_packets as usize,
-                    )(data)?
-                    .1,
+                    packets,
+                    damaged,
+                    truncation,
                 },
             ))
         })(input)
     }
+
+    pub fn into_owned(self) -> DataObject<'static> {
+        DataObject {
+            file_id: self.file_id,
+            total_data_packets: self.total_data_packets,
+            reserved: self.reserved,
+            packets: self
+                .packets
+                .into_iter()
+                .map(DataPacket::into_owned)
+                .collect(),
+            damaged: self.damaged,
+            truncation: self.truncation,
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let size: u64 = self.size_of().try_into()?;
+        w.write_all(&DATA_OBJECT.as_bytes_ms())?;
+        w.write_all(&size.to_le_bytes())?;
+        w.write_all(&self.file_id.as_bytes_ms())?;
+        w.write_all(&self.total_data_packets.to_le_bytes())?;
+        w.write_all(&self.reserved.to_le_bytes())?;
+        for packet in self.packets.iter() {
+            packet.write(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += 16;
+        len += 8;
+        len += 16;
+        len += 8;
+        len += 2;
+        for packet in self.packets.iter() {
+            len += packet.size_of();
+        }
+        len
+    }
+}
+
+/// Adds the packet index to an error. Running out of input here means the
+/// packet overran the data object.
+fn in_packet<'a, E: ParseError<Span<'a>>>(input: Span<'a>, index: usize, error: Err<E>) -> Err<E> {
+    let error = match error {
+        Err::Incomplete(needed) => Err::Error(overrun(input, needed)),
+        error => error,
+    };
+    error.map(|error| E::add_entry(input, "packet", index, error))
+}
+
+/// Describes a packet at `input` that needs more bytes than the data object
+/// has left, pointing at its length field if it has one and at its start if
+/// not.
+pub(crate) fn overrun<'a, E: ParseError<Span<'a>>>(input: Span<'a>, needed: Needed) -> E {
+    if let Some((field, _)) = DataPacket::length_field(input) {
+        return E::from_error_kind(field, ErrorKind::Verify);
+    }
+    let needed = match needed {
+        Needed::Size(needed) => needed.get(),
+        Needed::Unknown => 1,
+    };
+    let expected = input.len().saturating_add(needed) as u64;
+    E::from_size_mismatch(input, expected, input.len() as u64)
+}
+
+/// Checks whether a packet that ran out of input was cut off along with the
+/// file rather than damaged. The bytes it still needs must fit within the
+/// fixed packet size, if there is one, and within what is missing from the
+/// data object, if its size is known.
+fn is_cut_off(data: Span, needed: Needed, packet_len: u32, missing_bytes: Option<u64>) -> bool {
+    let needed = match needed {
+        Needed::Size(needed) => needed.get(),
+        Needed::Unknown => 1,
+    };
+    let claimed = data.len().saturating_add(needed);
+    (packet_len == 0 || claimed <= packet_len as usize)
+        && missing_bytes.is_none_or(|missing_bytes| needed as u64 <= missing_bytes)
+}
+
+/// Parses a damaged packet again to describe what is wrong with it, so the
+/// cost of building an `AsfError` is only paid for damaged packets.
+fn diagnose(data: Span, packet_len: u32, budget: &Budget) -> AsfError {
+    let budget = Budget::new(budget.limits);
+    match DataPacket::parse_with(data, packet_len.into(), &budget) {
+        Err(Err::Incomplete(needed)) => overrun(data, needed),
+        Err(Err::Error(error) | Err::Failure(error)) => error,
+        Ok(_) => AsfError::InvalidValue {
+            offset: data.location_offset(),
+            path: Vec::new(),
+        },
+    }
+}
+
+pub(crate) fn at_top_level_object(input: Span) -> bool {
+    match guid::<_, nom::error::Error<_>>(input) {
+        Ok((_, id)) => TOP_LEVEL_OBJECTS.contains(&id),
+        Err(_) => false,
+    }
+}
+
+/// Finds how many bytes to skip to reach the packet after a damaged one.
+fn next_packet(data: Span, packet_len: usize) -> usize {
+    if packet_len > 0 {
+        return packet_len.min(data.len());
+    }
+    (1..data.len())
+        .find(|&i| {
+            let candidate = data.slice(i..);
+            at_top_level_object(candidate)
+                || (DataPacket::is_plausible_start(candidate.fragment())
+                    && DataPacket::parser::<nom::error::Error<_>>(0)(candidate).is_ok())
+        })
+        .unwrap_or(data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::{find_guid, variable_size_wmv},
+        Container,
+    };
+
+    const BASIC_WMV: &[u8] = include_bytes!("../../samples/basic.wmv");
+
+    #[test]
+    fn recover_damaged_packets() {
+        let (_, original) =
+            Container::parse::<AsfError>(Span::new(BASIC_WMV)).expect("to parse successfully");
+        let mut buf = BASIC_WMV.to_vec();
+
+        // Overwrite the header of the third and fourth packets.
+        let packets = find_guid(&buf, DATA_OBJECT) + 50;
+        let packet_len = 1289;
+        for packet in 2..4 {
+            let start = packets + packet * packet_len;
+            buf[start..start + 16].fill(0xff);
+        }
+
+        Container::parse::<AsfError>(Span::new(&buf))
+            .expect_err("expected damaged packets to fail");
+        let options = ParseOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let (remaining, container) =
+            Container::parse_with_options::<AsfError>(Span::new(&buf), &options)
+                .expect("to parse successfully");
+        assert_eq!(remaining.len(), 0);
+        assert_eq!(container.data.packets.len(), 136);
+        assert_eq!(container.data.packets[..2], original.data.packets[..2]);
+        assert_eq!(container.data.packets[2..], original.data.packets[4..]);
+        assert_eq!(container.data.damaged.len(), 1);
+        assert_eq!(container.data.damaged[0].start, packets + 2 * packet_len);
+        assert_eq!(container.data.damaged[0].end, packets + 4 * packet_len);
+        assert_eq!(container.indices, original.indices);
+    }
+
+    #[test]
+    fn broadcast_wmv() {
+        let mut buf = BASIC_WMV.to_vec();
+
+        // Mark the file as a live broadcast with placeholder sizes and counts.
+        let props = find_guid(&buf, FILE_PROPERTIES_OBJECT) + 24;
+        buf[props + 16..props + 56].fill(0);
+        buf[props + 64] |= crate::header::file_properties::BROADCAST_FLAG as u8;
+        let data = find_guid(&buf, DATA_OBJECT);
+        buf[data + 16..data + 24].fill(0);
+        buf[data + 40..data + 48].fill(0);
+
+        let (remaining, container) =
+            Container::parse::<AsfError>(Span::new(&buf)).expect("to parse successfully");
+        assert_eq!(remaining.len(), 0);
+        assert_eq!(container.data.total_data_packets, 0);
+        assert_eq!(container.data.packets.len(), 138);
+        assert_eq!(
+            container.data.packets,
+            Container::parse::<AsfError>(Span::new(BASIC_WMV))
+                .unwrap()
+                .1
+                .data
+                .packets
+        );
+        assert!(!container.indices.objects.is_empty());
+    }
+
+    #[test]
+    fn truncated_wmv() {
+        // Cut the file off halfway through the eleventh packet.
+        let data = find_guid(BASIC_WMV, DATA_OBJECT);
+        let buf = &BASIC_WMV[..data + 50 + 1289 * 10 + 600];
+
+        let (remaining, container) =
+            Container::parse::<AsfError>(Span::new(buf)).expect("to parse successfully");
+        assert_eq!(remaining.len(), 0);
+        assert_eq!(container.data.packets.len(), 10);
+        assert_eq!(
+            container.data.truncation,
+            Some(Truncation {
+                missing_packets: 128,
+                missing_bytes: 1289 * 128 - 600,
+            })
+        );
+        assert!(container.indices.objects.is_empty());
+    }
+
+    #[test]
+    fn truncated_variable_size_wmv() {
+        // Cut the file off halfway through the eleventh packet.
+        let (mut buf, sizes) = variable_size_wmv();
+        let data = find_guid(&buf, DATA_OBJECT);
+        buf.truncate(data + 50 + sizes[..10].iter().sum::<usize>() + sizes[10] / 2);
+
+        let options = ParseOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let (remaining, container) =
+            Container::parse_with_options::<AsfError>(Span::new(&buf), &options)
+                .expect("to parse successfully");
+        assert_eq!(remaining.len(), 0);
+        assert_eq!(container.data.packets.len(), 10);
+        assert!(container.data.damaged.is_empty());
+        assert_eq!(
+            container.data.truncation,
+            Some(Truncation {
+                missing_packets: 128,
+                missing_bytes: (sizes[10..].iter().sum::<usize>() - sizes[10] / 2) as u64,
+            })
+        );
+    }
 }
@@ -0,0 +1,241 @@
+//! Packing payloads into new fixed-size packets, for files whose packets have
+//! been taken apart to drop, add or retime payloads.
+
+use super::packets::*;
+use std::{borrow::Cow, convert::TryInto};
+
+/// Written at the start of every packet, as Windows Media encoders do.
+const ERROR_CORRECTION: ErrorCorrectionData = ErrorCorrectionData {
+    flags: 0x82,
+    ec_type: 0,
+    ec_cycle: 0,
+};
+
+/// The most payloads the payload flags can count.
+const MAX_PAYLOADS: usize = 0x3f;
+
+/// Takes the payloads out of `packets`, each with the send time of its
+/// packet in milliseconds.
+pub fn unpack<'a>(packets: Vec<DataPacket<'a>>) -> Vec<(u32, Payload<'a>)> {
+    packets
+        .into_iter()
+        .flat_map(|packet| {
+            let send_time = packet.payload_parsing_data.send_time;
+            let payloads = match packet.payload {
+                PayloadData::SinglePayload(payload) => vec![payload],
+                PayloadData::MultiplePayloads { payloads, .. } => payloads,
+            };
+            payloads
+                .into_iter()
+                .map(move |payload| (send_time, payload))
+        })
+        .collect()
+}
+
+/// Packs payloads, given in send order with their send times, into packets
+/// of exactly `packet_len` bytes. Each packet is sent at the time of its
+/// first payload. Normal payloads that do not fit in the rest of a packet
+/// are split across packets.
+pub fn packetize<'a>(
+    payloads: Vec<(u32, Payload<'a>)>,
+    packet_len: u32,
+) -> Result<Vec<DataPacket<'a>>, Box<dyn std::error::Error>> {
+    let len_type = match packet_len {
+        0..=0xffff => FieldType::Word,
+        _ => FieldType::Dword,
+    };
+    let template = PayloadParsingData {
+        length_type_flags: LengthTypeFlags {
+            multiple_payloads_present: MultiplePayloadsFlag::MultiplePayloads,
+            sequence_type: FieldType::None,
+            padding_len_type: len_type,
+            packet_len_type: FieldType::None,
+            error_correction_flag: ErrorCorrectionFlag::Absent,
+        },
+        property_flags: property_flags(&payloads),
+        packet_length: 0,
+        sequence: 0,
+        padding_len: 0,
+        send_time: 0,
+        duration: 0,
+    };
+    let property_flags = template.property_flags;
+    let packet_len: usize = packet_len.try_into()?;
+    // The error correction data, payload parsing data and payload flags.
+    let overhead = ERROR_CORRECTION.size_of() + template.size_of() + 1;
+
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut send_time = 0;
+    let mut used = overhead;
+    let mut finish = |current: &mut Vec<Payload<'a>>, send_time: u32, used: usize| {
+        packets.push(DataPacket {
+            error_correction_data: Some(ERROR_CORRECTION),
+            payload_parsing_data: PayloadParsingData {
+                padding_len: (packet_len - used).try_into()?,
+                send_time,
+                ..template
+            },
+            payload: PayloadData::MultiplePayloads {
+                payload_len_type: len_type,
+                payloads: std::mem::take(current),
+            },
+        });
+        Ok::<_, Box<dyn std::error::Error>>(overhead)
+    };
+
+    for (time, mut payload) in payloads {
+        loop {
+            if current.is_empty() {
+                send_time = time;
+            }
+            let len = payload.size_of(property_flags, Some(len_type));
+            if used + len <= packet_len && current.len() < MAX_PAYLOADS {
+                used += len;
+                current.push(payload);
+                break;
+            }
+            if current.len() < MAX_PAYLOADS && used < packet_len {
+                if let Payload::NormalPayload { payload_data, .. } = &payload {
+                    let room = packet_len - used;
+                    let header_len = len - payload_data.len();
+                    if room > header_len {
+                        let (head, tail) = split(payload, room - header_len);
+                        current.push(head);
+                        used = finish(&mut current, send_time, packet_len)?;
+                        payload = tail;
+                        continue;
+                    }
+                }
+            }
+            if current.is_empty() {
+                return Err("payload does not fit in a packet".into());
+            }
+            used = finish(&mut current, send_time, used)?;
+        }
+    }
+    if !current.is_empty() {
+        finish(&mut current, send_time, used)?;
+    }
+    Ok(packets)
+}
+
+/// The narrowest property flags that fit every payload, with room for the
+/// offsets of split payloads.
+fn property_flags(payloads: &[(u32, Payload)]) -> PropertyFlags {
+    let mut media_object_number = 0;
+    let mut replicated_data_len = 1;
+    for (_, payload) in payloads.iter() {
+        media_object_number = media_object_number.max(payload.media_object_number());
+        if let Payload::NormalPayload {
+            replicated_data, ..
+        } = payload
+        {
+            replicated_data_len = replicated_data_len.max(replicated_data.len());
+        }
+    }
+    PropertyFlags {
+        replicated_data_len_type: field_type(replicated_data_len as u64),
+        offset_into_media_object_type: FieldType::Dword,
+        media_object_number_len_type: field_type(media_object_number.into()),
+        stream_number_len_type: FieldType::Byte,
+    }
+}
+
+fn field_type(max: u64) -> FieldType {
+    match max {
+        0..=0xff => FieldType::Byte,
+        0x100..=0xffff => FieldType::Word,
+        _ => FieldType::Dword,
+    }
+}
+
+/// Splits a normal payload after `at` bytes of payload data.
+fn split(payload: Payload, at: usize) -> (Payload, Payload) {
+    match payload {
+        Payload::NormalPayload {
+            stream_flags,
+            media_object_number,
+            offset_into_media_object,
+            replicated_data,
+            payload_data,
+        } => {
+            let (head, tail) = match payload_data {
+                Cow::Borrowed(data) => (Cow::Borrowed(&data[..at]), Cow::Borrowed(&data[at..])),
+                Cow::Owned(mut data) => {
+                    let tail = data.split_off(at);
+                    (Cow::Owned(data), Cow::Owned(tail))
+                }
+            };
+            let head = Payload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data: replicated_data.clone(),
+                payload_data: head,
+            };
+            let tail = Payload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object: offset_into_media_object + at as u32,
+                replicated_data,
+                payload_data: tail,
+            };
+            (head, tail)
+        }
+        Payload::CompressedPayload { .. } => unreachable!("compressed payloads are not split"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC_WMV: &[u8] = include_bytes!("../../samples/basic.wmv");
+
+    /// The payload data of each stream, joined in order.
+    fn stream_data(packets: &[DataPacket]) -> Vec<(u8, Vec<u8>)> {
+        let mut streams: Vec<(u8, Vec<u8>)> = Vec::new();
+        for payload in packets.iter().flat_map(|packet| packet.payload.payloads()) {
+            let number = payload.stream_flags().stream_number;
+            let data = match payload {
+                Payload::NormalPayload { payload_data, .. } => payload_data.to_vec(),
+                Payload::CompressedPayload {
+                    sub_payload_data, ..
+                } => sub_payload_data.concat(),
+            };
+            match streams.iter_mut().find(|(n, _)| *n == number) {
+                Some((_, stream)) => stream.extend(data),
+                None => streams.push((number, data)),
+            }
+        }
+        streams
+    }
+
+    #[test]
+    fn repacketize() {
+        let mut container = crate::parse(BASIC_WMV).unwrap();
+        let expected = stream_data(&container.data.packets);
+        let packet_len = container
+            .header
+            .file_properties()
+            .unwrap()
+            .maximum_data_packet_size;
+
+        for &len in [packet_len / 3, packet_len].iter() {
+            let packets = std::mem::take(&mut container.data.packets);
+            let packets = packetize(unpack(packets), len).expect("to packetize");
+            assert!(packets
+                .iter()
+                .all(|packet| packet.size_of() == len as usize));
+            assert_eq!(stream_data(&packets), expected);
+            container.data.packets = packets;
+        }
+        crate::finalize::finalize(&mut container).unwrap();
+
+        let mut out = Vec::new();
+        container.write(&mut out).unwrap();
+        let reparsed = crate::parse(&out).unwrap();
+        assert_eq!(stream_data(&reparsed.data.packets), expected);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
-    combinators::{length_take, span_bytes},
-    error::Error,
+    combinators::{count, length_take, span_bytes},
+    error::{BitParseError, ParseError},
+    options::Budget,
     span::Span,
 };
 use nom::{
@@ -12,10 +13,11 @@ use nom::{
     bytes::streaming::take,
     combinator::{complete, map, peek, rest, rest_len, value},
     error::context,
-    multi::{count, many0},
+    multi::many0,
     number::streaming::{le_u16, le_u32, le_u8},
-    IResult,
+    Err, IResult, Slice,
 };
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MultiplePayloadsFlag {
@@ -39,56 +41,56 @@ pub enum FieldType {
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct LengthTypeFlags {
-    multiple_payloads_present: MultiplePayloadsFlag,
-    sequence_type: FieldType,
-    padding_len_type: FieldType,
-    packet_len_type: FieldType,
-    error_correction_flag: ErrorCorrectionFlag,
+    pub multiple_payloads_present: MultiplePayloadsFlag,
+    pub sequence_type: FieldType,
+    pub padding_len_type: FieldType,
+    pub packet_len_type: FieldType,
+    pub error_correction_flag: ErrorCorrectionFlag,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PropertyFlags {
-    replicated_data_len_type: FieldType,
-    offset_into_media_object_type: FieldType,
-    media_object_number_len_type: FieldType,
-    stream_number_len_type: FieldType,
+    pub replicated_data_len_type: FieldType,
+    pub offset_into_media_object_type: FieldType,
+    pub media_object_number_len_type: FieldType,
+    pub stream_number_len_type: FieldType,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct PayloadFlags {
-    number_of_payloads: u8,
-    payload_len_type: FieldType,
+    pub number_of_payloads: u8,
+    pub payload_len_type: FieldType,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct StreamFlags {
-    stream_number: u8,
-    key_frame: bool,
+    pub stream_number: u8,
+    pub key_frame: bool,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct DataPacket<'a> {
-    error_correction_data: Option<ErrorCorrectionData>,
-    payload_parsing_data: PayloadParsingData,
-    payload: PayloadData<'a>,
+    pub error_correction_data: Option<ErrorCorrectionData>,
+    pub payload_parsing_data: PayloadParsingData,
+    pub payload: PayloadData<'a>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ErrorCorrectionData {
-    flags: u8,
-    ec_type: u8,
-    ec_cycle: u8,
+    pub flags: u8,
+    pub ec_type: u8,
+    pub ec_cycle: u8,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PayloadParsingData {
-    length_type_flags: LengthTypeFlags,
-    property_flags: PropertyFlags,
-    packet_length: u32,
-    sequence: u32,
-    padding_len: u32,
-    send_time: u32,
-    duration: u16,
+    pub length_type_flags: LengthTypeFlags,
+    pub property_flags: PropertyFlags,
+    pub packet_length: u32,
+    pub sequence: u32,
+    pub padding_len: u32,
+    pub send_time: u32,
+    pub duration: u16,
 }
 
 #[derive(Debug, PartialEq)]
@@ -97,30 +99,33 @@ pub enum Payload<'a> {
         stream_flags: StreamFlags,
         media_object_number: u32,
         offset_into_media_object: u32,
-        replicated_data: &'a [u8],
-        payload_data: &'a [u8],
+        replicated_data: Cow<'a, [u8]>,
+        payload_data: Cow<'a, [u8]>,
     },
     CompressedPayload {
         stream_flags: StreamFlags,
         media_object_number: u32,
         presentation_time: u32,
         presentation_time_delta: u8,
-        sub_payload_data: Vec<&'a [u8]>,
+        sub_payload_data: Vec<Cow<'a, [u8]>>,
     },
 }
 
 #[derive(Debug, PartialEq)]
 pub enum PayloadData<'a> {
     SinglePayload(Payload<'a>),
-    MultiplePayloads(Vec<Payload<'a>>),
+    MultiplePayloads {
+        payload_len_type: FieldType,
+        payloads: Vec<Payload<'a>>,
+    },
 }
 
 impl MultiplePayloadsFlag {
-    pub fn parse(
-        data: (Span, usize),
-    ) -> IResult<(Span, usize), MultiplePayloadsFlag, Error<(Span, usize)>> {
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
+        data: (Span<'a>, usize),
+    ) -> IResult<(Span<'a>, usize), MultiplePayloadsFlag, E> {
         context(
-            "MultiplePayloadsFlag",
+            "multiple payloads flag",
             alt((
                 value(MultiplePayloadsFlag::SinglePayload, tag_bits(0, 1usize)),
                 value(MultiplePayloadsFlag::MultiplePayloads, tag_bits(1, 1usize)),
@@ -130,12 +135,12 @@ impl MultiplePayloadsFlag {
 }
 
 impl ErrorCorrectionFlag {
-    pub fn parse(
-        data: (Span, usize),
-    ) -> IResult<(Span, usize), ErrorCorrectionFlag, Error<(Span, usize)>> {
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
+        data: (Span<'a>, usize),
+    ) -> IResult<(Span<'a>, usize), ErrorCorrectionFlag, E> {
         context(
-            "ErrorCorrectionFlag",
-            nom::combinator::map(nom::bits::complete::take(1usize), |x: u8| match x == 1 {
+            "error correction flag",
+            nom::combinator::map(take_bits(1usize), |x: u8| match x == 1 {
                 true => ErrorCorrectionFlag::Present,
                 false => ErrorCorrectionFlag::Absent,
             }),
@@ -144,10 +149,12 @@ impl ErrorCorrectionFlag {
 }
 
 impl FieldType {
-    pub fn parse(input: (Span, usize)) -> IResult<(Span, usize), Self, Error<(Span, usize)>> {
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
+        input: (Span<'a>, usize),
+    ) -> IResult<(Span<'a>, usize), Self, E> {
         context(
-            "FieldType",
-            nom::combinator::map(nom::bits::complete::take(2usize), |x: u8| match x {
+            "field type",
+            nom::combinator::map(take_bits(2usize), |x: u8| match x {
                 1 => Self::Byte,
                 2 => Self::Word,
                 3 => Self::Dword,
@@ -156,23 +163,57 @@ impl FieldType {
         )(input)
     }
 
-    pub fn field<'a>(self) -> impl Fn(Span<'a>) -> IResult<Span<'a>, u32, Error<Span<'a>>> {
-        move |input: Span| -> IResult<Span, u32, Error<Span<'a>>> {
+    pub fn field<'a, E: ParseError<Span<'a>>>(
+        self,
+    ) -> impl Fn(Span<'a>) -> IResult<Span<'a>, u32, E> {
+        move |input: Span<'a>| -> IResult<Span<'a>, u32, E> {
             match self {
                 Self::None => Ok((input, 0)),
                 Self::Byte => map(le_u8, |x| x as u32)(input),
                 Self::Word => map(le_u16, |x| x as u32)(input),
-                Self::Dword => map(le_u32, |x| x as u32)(input),
+                Self::Dword => le_u32(input),
             }
         }
     }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Byte => 1,
+            Self::Word => 2,
+            Self::Dword => 3,
+        }
+    }
+
+    pub fn write_field<T: Write>(
+        self,
+        w: &mut T,
+        value: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::None => {}
+            Self::Byte => w.write_all(&TryInto::<u8>::try_into(value)?.to_le_bytes())?,
+            Self::Word => w.write_all(&TryInto::<u16>::try_into(value)?.to_le_bytes())?,
+            Self::Dword => w.write_all(&value.to_le_bytes())?,
+        }
+        Ok(())
+    }
+
+    pub fn size_of_field(self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Byte => 1,
+            Self::Word => 2,
+            Self::Dword => 4,
+        }
+    }
 }
 
 impl LengthTypeFlags {
-    pub fn parse<'a>(
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
         input: (Span<'a>, usize),
-    ) -> IResult<(Span<'a>, usize), Self, Error<(Span<'a>, usize)>> {
-        context("LengthTypeFlags", move |input: (Span<'a>, usize)| {
+    ) -> IResult<(Span<'a>, usize), Self, E> {
+        context("length type flags", move |input: (Span<'a>, usize)| {
             let (input, error_correction_flag) = ErrorCorrectionFlag::parse(input)?;
             let (input, packet_len_type) = FieldType::parse(input)?;
             let (input, padding_len_type) = FieldType::parse(input)?;
@@ -190,13 +231,32 @@ impl LengthTypeFlags {
             ))
         })(input)
     }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let mut flags = 0u8;
+        if self.error_correction_flag == ErrorCorrectionFlag::Present {
+            flags |= 0x80;
+        }
+        flags |= self.packet_len_type.bits() << 5;
+        flags |= self.padding_len_type.bits() << 3;
+        flags |= self.sequence_type.bits() << 1;
+        if self.multiple_payloads_present == MultiplePayloadsFlag::MultiplePayloads {
+            flags |= 0x01;
+        }
+        w.write_all(&[flags])?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        1
+    }
 }
 
 impl PropertyFlags {
-    pub fn parse<'a>(
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
         input: (Span<'a>, usize),
-    ) -> IResult<(Span<'a>, usize), Self, Error<(Span<'a>, usize)>> {
-        context("PropertyFlags", move |input: (Span<'a>, usize)| {
+    ) -> IResult<(Span<'a>, usize), Self, E> {
+        context("property flags", move |input: (Span<'a>, usize)| {
             let (input, stream_number_len_type) = FieldType::parse(input)?;
             let (input, media_object_number_len_type) = FieldType::parse(input)?;
             let (input, offset_into_media_object_len_type) = FieldType::parse(input)?;
@@ -212,13 +272,27 @@ impl PropertyFlags {
             ))
         })(input)
     }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let mut flags = 0u8;
+        flags |= self.stream_number_len_type.bits() << 6;
+        flags |= self.media_object_number_len_type.bits() << 4;
+        flags |= self.offset_into_media_object_type.bits() << 2;
+        flags |= self.replicated_data_len_type.bits();
+        w.write_all(&[flags])?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        1
+    }
 }
 
 impl PayloadFlags {
-    pub fn parse<'a>(
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
         input: (Span<'a>, usize),
-    ) -> IResult<(Span<'a>, usize), Self, Error<(Span<'a>, usize)>> {
-        context("PayloadFlags", move |input: (Span<'a>, usize)| {
+    ) -> IResult<(Span<'a>, usize), Self, E> {
+        context("payload flags", move |input: (Span<'a>, usize)| {
             let (input, payload_len_type) = FieldType::parse(input)?;
             let (input, number_of_payloads) = take_bits(6usize)(input)?;
             Ok((
@@ -230,13 +304,25 @@ impl PayloadFlags {
             ))
         })(input)
     }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        if self.number_of_payloads > 0x3f {
+            return Err("too many payloads in packet".into());
+        }
+        w.write_all(&[self.payload_len_type.bits() << 6 | self.number_of_payloads])?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        1
+    }
 }
 
 impl StreamFlags {
-    pub fn parse<'a>(
+    pub fn parse<'a, E: BitParseError<Span<'a>>>(
         input: (Span<'a>, usize),
-    ) -> IResult<(Span<'a>, usize), Self, Error<(Span<'a>, usize)>> {
-        context("StreamFlags", move |input: (Span<'a>, usize)| {
+    ) -> IResult<(Span<'a>, usize), Self, E> {
+        context("stream flags", move |input: (Span<'a>, usize)| {
             let (input, key_frame) = map(take_bits(1usize), |x: u8| x == 1)(input)?;
             let (input, stream_number) = take_bits(7usize)(input)?;
             Ok((
@@ -248,52 +334,157 @@ impl StreamFlags {
             ))
         })(input)
     }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        if self.stream_number > 0x7f {
+            return Err("stream number out of range".into());
+        }
+        w.write_all(&[(self.key_frame as u8) << 7 | self.stream_number])?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        1
+    }
 }
 
 impl<'a> DataPacket<'a> {
-    pub fn parser(
-        total_data_packets: u64,
-        total_packet_len: u64,
-    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, DataPacket, Error<Span<'a>>> {
-        let fixed_packet_len = total_packet_len / total_data_packets;
-        context("DataPacket", move |input: Span<'a>| {
-            let initial_remainder = rest_len(input)?.1;
-            let (input, error_correction_present) = map(peek(le_u8), |x| x & 0x80 != 0)(input)?;
-            let (input, error_correction_data) =
-                nom::combinator::cond(error_correction_present, ErrorCorrectionData::parse)(input)?;
-            let (input, payload_parsing_data) = PayloadParsingData::parse(input)?;
-            let header_len = initial_remainder - rest_len(input)?.1;
-            let (input, raw_payload) = match payload_parsing_data.packet_length {
-                0 => take(
-                    fixed_packet_len - header_len as u64 - payload_parsing_data.padding_len as u64,
-                )(input)?,
-                _ => take(payload_parsing_data.packet_length)(input)?,
-            };
-            let payload_parser = {
-                PayloadData::parser(
-                    payload_parsing_data
-                        .length_type_flags
-                        .multiple_payloads_present,
-                    payload_parsing_data.property_flags,
-                )
-            };
-            let payload = payload_parser(raw_payload)?.1;
-            let (input, _) = take(payload_parsing_data.padding_len)(input)?;
-            Ok((
+    /// The fewest bytes a packet can take: the length type and property
+    /// flags, the send time and the duration.
+    pub(crate) const MIN_SIZE: usize = 8;
+
+    pub fn parser<E: ParseError<Span<'a>>>(
+        fixed_packet_len: u64,
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, DataPacket<'a>, E> {
+        let budget = Budget::default();
+        move |input: Span<'a>| Self::parse_with(input, fixed_packet_len, &budget)
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        fixed_packet_len: u64,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, DataPacket<'a>, E> {
+        let start = input;
+        let initial_remainder = rest_len(input)?.1;
+        let (input, (error_correction_data, payload_parsing_data)) = Self::parse_header(input)?;
+        let header_len = initial_remainder - rest_len(input)?.1;
+        let packet_len = match payload_parsing_data.packet_length {
+            0 => fixed_packet_len,
+            packet_length => packet_length.into(),
+        };
+        let payload_len = packet_len
+            .checked_sub(header_len as u64)
+            .and_then(|len| len.checked_sub(payload_parsing_data.padding_len.into()))
+            .ok_or_else(|| {
+                let expected = header_len as u64 + u64::from(payload_parsing_data.padding_len);
+                nom::Err::Error(E::from_size_mismatch(start, expected, packet_len))
+            })?;
+        let (input, raw_payload) = take(payload_len)(input)?;
+        let payload = within(|input| {
+            PayloadData::parse_with(
                 input,
-                DataPacket {
-                    error_correction_data,
-                    payload_parsing_data,
-                    payload,
-                },
-            ))
-        })
+                payload_parsing_data
+                    .length_type_flags
+                    .multiple_payloads_present,
+                payload_parsing_data.property_flags,
+                budget,
+            )
+        })(raw_payload)?
+        .1;
+        let (input, _) = take(payload_parsing_data.padding_len)(input)?;
+        Ok((
+            input,
+            DataPacket {
+                error_correction_data,
+                payload_parsing_data,
+                payload,
+            },
+        ))
+    }
+
+    fn parse_header<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+    ) -> IResult<Span<'a>, (Option<ErrorCorrectionData>, PayloadParsingData), E> {
+        let (input, error_correction_present) = map(peek(le_u8), |x| x & 0x80 != 0)(input)?;
+        let (input, error_correction_data) =
+            nom::combinator::cond(error_correction_present, ErrorCorrectionData::parse)(input)?;
+        let (input, payload_parsing_data) = PayloadParsingData::parse(input)?;
+        Ok((input, (error_correction_data, payload_parsing_data)))
+    }
+
+    /// Finds the packet length field of the packet starting at `input`, if
+    /// it has one, along with the length it gives, so errors about the
+    /// packet's size can point at it.
+    pub(crate) fn length_field(input: Span<'a>) -> Option<(Span<'a>, u32)> {
+        let (_, (error_correction_data, payload_parsing_data)) =
+            Self::parse_header::<nom::error::Error<_>>(input).ok()?;
+        if payload_parsing_data.length_type_flags.packet_len_type == FieldType::None {
+            return None;
+        }
+        // The length follows the two flag bytes.
+        let start = error_correction_data.map_or(0, |data| data.size_of()) + 2;
+        Some((input.slice(start..), payload_parsing_data.packet_length))
+    }
+
+    /// Checks whether a variable-size packet could plausibly start with the
+    /// given bytes. Used to find the next packet after a damaged one.
+    pub fn is_plausible_start(data: &[u8]) -> bool {
+        let data = match data {
+            [0x82, _, _, rest @ ..] => rest,
+            [flags, ..] if flags & 0x80 != 0 => return false,
+            _ => data,
+        };
+        match data {
+            [length_type_flags, property_flags, ..] => {
+                // Variable-size packets must specify their length, and the
+                // stream number length type is always a byte.
+                length_type_flags & 0x80 == 0
+                    && length_type_flags & 0x60 != 0
+                    && property_flags >> 6 == 1
+            }
+            _ => false,
+        }
+    }
+
+    pub fn into_owned(self) -> DataPacket<'static> {
+        DataPacket {
+            error_correction_data: self.error_correction_data,
+            payload_parsing_data: self.payload_parsing_data,
+            payload: self.payload.into_owned(),
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(error_correction_data) = &self.error_correction_data {
+            error_correction_data.write(w)?;
+        }
+        self.payload_parsing_data.write(w)?;
+        self.payload
+            .write(w, self.payload_parsing_data.property_flags)?;
+        w.write_all(&vec![0u8; self.payload_parsing_data.padding_len as usize])?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        if let Some(error_correction_data) = &self.error_correction_data {
+            len += error_correction_data.size_of();
+        }
+        len += self.payload_parsing_data.size_of();
+        len += self
+            .payload
+            .size_of(self.payload_parsing_data.property_flags);
+        len += self.payload_parsing_data.padding_len as usize;
+        len
     }
 }
 
 impl ErrorCorrectionData {
-    pub fn parse<'a>(input: Span<'a>) -> IResult<Span<'a>, ErrorCorrectionData, Error<Span<'a>>> {
-        context("ErrorCorrectionData", move |input: Span<'a>| {
+    pub fn parse<'a, E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+    ) -> IResult<Span<'a>, ErrorCorrectionData, E> {
+        context("error correction data", move |input: Span<'a>| {
             let (input, flags) = le_u8(input)?;
             let (input, ec_type) = le_u8(input)?;
             let (input, ec_cycle) = le_u8(input)?;
@@ -307,13 +498,31 @@ impl ErrorCorrectionData {
             ))
         })(input)
     }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        w.write_all(&self.flags.to_le_bytes())?;
+        w.write_all(&self.ec_type.to_le_bytes())?;
+        w.write_all(&self.ec_cycle.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += 1;
+        len += 1;
+        len += 1;
+        len
+    }
 }
 
 impl PayloadParsingData {
-    pub fn parse<'a>(input: Span<'a>) -> IResult<Span<'a>, PayloadParsingData, Error<Span<'a>>> {
-        context("PayloadParsingData", move |input: Span<'a>| {
-            let (input, length_type_flags) = bits(LengthTypeFlags::parse)(input)?;
-            let (input, property_flags) = bits(PropertyFlags::parse)(input)?;
+    pub fn parse<'a, E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+    ) -> IResult<Span<'a>, PayloadParsingData, E> {
+        context("payload parsing data", move |input: Span<'a>| {
+            let (input, length_type_flags) =
+                bits::<_, _, E::Bits, _, _>(LengthTypeFlags::parse)(input)?;
+            let (input, property_flags) = bits::<_, _, E::Bits, _, _>(PropertyFlags::parse)(input)?;
             let (input, packet_length) = length_type_flags.packet_len_type.field()(input)?;
             let (input, sequence) = length_type_flags.sequence_type.field()(input)?;
             let (input, padding_len) = length_type_flags.padding_len_type.field()(input)?;
@@ -333,48 +542,177 @@ impl PayloadParsingData {
             ))
         })(input)
     }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        self.length_type_flags.write(w)?;
+        self.property_flags.write(w)?;
+        self.length_type_flags
+            .packet_len_type
+            .write_field(w, self.packet_length)?;
+        self.length_type_flags
+            .sequence_type
+            .write_field(w, self.sequence)?;
+        self.length_type_flags
+            .padding_len_type
+            .write_field(w, self.padding_len)?;
+        w.write_all(&self.send_time.to_le_bytes())?;
+        w.write_all(&self.duration.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += self.length_type_flags.size_of();
+        len += self.property_flags.size_of();
+        len += self.length_type_flags.packet_len_type.size_of_field();
+        len += self.length_type_flags.sequence_type.size_of_field();
+        len += self.length_type_flags.padding_len_type.size_of_field();
+        len += 4;
+        len += 2;
+        len
+    }
 }
 
 impl<'a> PayloadData<'a> {
-    pub fn parser(
+    pub fn parser<E: ParseError<Span<'a>>>(
         multiple: MultiplePayloadsFlag,
         property_flags: PropertyFlags,
-    ) -> impl Fn(Span<'a>) -> IResult<Span<'a>, PayloadData<'a>, Error<Span<'a>>> {
+    ) -> impl Fn(Span<'a>) -> IResult<Span<'a>, PayloadData<'a>, E> {
         move |input: Span| match multiple {
             MultiplePayloadsFlag::SinglePayload => Self::parser_single(property_flags)(input),
             MultiplePayloadsFlag::MultiplePayloads => Self::parser_multi(property_flags)(input),
         }
     }
 
-    pub fn parser_single(
+    pub fn parser_single<E: ParseError<Span<'a>>>(
         property_flags: PropertyFlags,
-    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, PayloadData<'a>, Error<Span<'a>>> {
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, PayloadData<'a>, E> {
         map(Payload::parser(property_flags, None), |x| {
             PayloadData::SinglePayload(x)
         })
     }
 
-    pub fn parser_multi(
+    pub fn parser_multi<E: ParseError<Span<'a>>>(
         property_flags: PropertyFlags,
-    ) -> impl Fn(Span<'a>) -> IResult<Span<'a>, PayloadData<'a>, Error<Span<'a>>> {
-        move |input: Span| {
-            let (input, payload_flags) = bits(PayloadFlags::parse)(input)?;
-            let (input, payloads) = count(
-                Payload::parser(property_flags, Some(payload_flags.payload_len_type)),
-                payload_flags.number_of_payloads.into(),
-            )(input)?;
-            Ok((input, PayloadData::MultiplePayloads(payloads)))
+    ) -> impl Fn(Span<'a>) -> IResult<Span<'a>, PayloadData<'a>, E> {
+        move |input: Span| Self::parse_multi_with(input, property_flags, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        multiple: MultiplePayloadsFlag,
+        property_flags: PropertyFlags,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, PayloadData<'a>, E> {
+        match multiple {
+            MultiplePayloadsFlag::SinglePayload => Self::parser_single(property_flags)(input),
+            MultiplePayloadsFlag::MultiplePayloads => {
+                Self::parse_multi_with(input, property_flags, budget)
+            }
+        }
+    }
+
+    fn parse_multi_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        property_flags: PropertyFlags,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, PayloadData<'a>, E> {
+        let (input, payload_flags) = bits::<_, _, E::Bits, _, _>(PayloadFlags::parse)(input)?;
+        let number_of_payloads = payload_flags.number_of_payloads.into();
+        let max = budget.limits.max_payloads_per_packet;
+        budget.reserve::<Payload, _>(input, number_of_payloads, max)?;
+        let (input, payloads) = count(
+            within(Payload::parser(
+                property_flags,
+                Some(payload_flags.payload_len_type),
+            )),
+            number_of_payloads,
+        )(input)?;
+        Ok((
+            input,
+            PayloadData::MultiplePayloads {
+                payload_len_type: payload_flags.payload_len_type,
+                payloads,
+            },
+        ))
+    }
+
+    pub fn into_owned(self) -> PayloadData<'static> {
+        match self {
+            PayloadData::SinglePayload(payload) => PayloadData::SinglePayload(payload.into_owned()),
+            PayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads,
+            } => PayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads: payloads.into_iter().map(Payload::into_owned).collect(),
+            },
+        }
+    }
+
+    pub fn write<T: Write>(
+        &self,
+        w: &mut T,
+        property_flags: PropertyFlags,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            PayloadData::SinglePayload(payload) => payload.write(w, property_flags, None)?,
+            PayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads,
+            } => {
+                PayloadFlags {
+                    number_of_payloads: payloads.len().try_into()?,
+                    payload_len_type: *payload_len_type,
+                }
+                .write(w)?;
+                for payload in payloads.iter() {
+                    payload.write(w, property_flags, Some(*payload_len_type))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size_of(&self, property_flags: PropertyFlags) -> usize {
+        match self {
+            PayloadData::SinglePayload(payload) => payload.size_of(property_flags, None),
+            PayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads,
+            } => {
+                let mut len = 0;
+                len += 1;
+                for payload in payloads.iter() {
+                    len += payload.size_of(property_flags, Some(*payload_len_type));
+                }
+                len
+            }
+        }
+    }
+
+    pub fn payloads(&self) -> &[Payload<'a>] {
+        match self {
+            PayloadData::SinglePayload(payload) => std::slice::from_ref(payload),
+            PayloadData::MultiplePayloads { payloads, .. } => payloads,
+        }
+    }
+
+    pub fn payloads_mut(&mut self) -> &mut [Payload<'a>] {
+        match self {
+            PayloadData::SinglePayload(payload) => std::slice::from_mut(payload),
+            PayloadData::MultiplePayloads { payloads, .. } => payloads,
         }
     }
 }
 
 impl<'a> Payload<'a> {
-    pub fn parser(
+    pub fn parser<E: ParseError<Span<'a>>>(
         property_flags: PropertyFlags,
         payload_length_type: Option<FieldType>,
-    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Payload, Error<Span<'a>>> {
-        context("Payload", move |input: Span<'a>| {
-            let (input, stream_flags) = bits(StreamFlags::parse)(input)?;
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Payload, E> {
+        context("payload", move |input: Span<'a>| {
+            let (input, stream_flags) = bits::<_, _, E::Bits, _, _>(StreamFlags::parse)(input)?;
             let (input, media_object_number) =
                 property_flags.media_object_number_len_type.field()(input)?;
             let (input, time_or_offset) =
@@ -423,4 +761,227 @@ impl<'a> Payload<'a> {
             }
         })
     }
+
+    pub fn into_owned(self) -> Payload<'static> {
+        match self {
+            Payload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data,
+                payload_data,
+            } => Payload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data: Cow::Owned(replicated_data.into_owned()),
+                payload_data: Cow::Owned(payload_data.into_owned()),
+            },
+            Payload::CompressedPayload {
+                stream_flags,
+                media_object_number,
+                presentation_time,
+                presentation_time_delta,
+                sub_payload_data,
+            } => Payload::CompressedPayload {
+                stream_flags,
+                media_object_number,
+                presentation_time,
+                presentation_time_delta,
+                sub_payload_data: sub_payload_data
+                    .into_iter()
+                    .map(|data| Cow::Owned(data.into_owned()))
+                    .collect(),
+            },
+        }
+    }
+
+    pub fn write<T: Write>(
+        &self,
+        w: &mut T,
+        property_flags: PropertyFlags,
+        payload_len_type: Option<FieldType>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Payload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data,
+                payload_data,
+            } => {
+                stream_flags.write(w)?;
+                property_flags
+                    .media_object_number_len_type
+                    .write_field(w, *media_object_number)?;
+                property_flags
+                    .offset_into_media_object_type
+                    .write_field(w, *offset_into_media_object)?;
+                property_flags
+                    .replicated_data_len_type
+                    .write_field(w, replicated_data.len().try_into()?)?;
+                w.write_all(replicated_data)?;
+                if let Some(len_type) = payload_len_type {
+                    len_type.write_field(w, payload_data.len().try_into()?)?;
+                }
+                w.write_all(payload_data)?;
+            }
+            Payload::CompressedPayload {
+                stream_flags,
+                media_object_number,
+                presentation_time,
+                presentation_time_delta,
+                sub_payload_data,
+            } => {
+                stream_flags.write(w)?;
+                property_flags
+                    .media_object_number_len_type
+                    .write_field(w, *media_object_number)?;
+                property_flags
+                    .offset_into_media_object_type
+                    .write_field(w, *presentation_time)?;
+                property_flags.replicated_data_len_type.write_field(w, 1)?;
+                w.write_all(&presentation_time_delta.to_le_bytes())?;
+                if let Some(len_type) = payload_len_type {
+                    let sub_payloads_len: usize =
+                        sub_payload_data.iter().map(|x| 1 + x.len()).sum();
+                    len_type.write_field(w, sub_payloads_len.try_into()?)?;
+                }
+                for sub_payload in sub_payload_data.iter() {
+                    let sub_payload_len: u8 = sub_payload.len().try_into()?;
+                    w.write_all(&sub_payload_len.to_le_bytes())?;
+                    w.write_all(sub_payload)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size_of(
+        &self,
+        property_flags: PropertyFlags,
+        payload_len_type: Option<FieldType>,
+    ) -> usize {
+        let mut len = 0;
+        len += 1;
+        len += property_flags.media_object_number_len_type.size_of_field();
+        len += property_flags.offset_into_media_object_type.size_of_field();
+        len += property_flags.replicated_data_len_type.size_of_field();
+        len += payload_len_type.map_or(0, |x| x.size_of_field());
+        match self {
+            Payload::NormalPayload {
+                replicated_data,
+                payload_data,
+                ..
+            } => {
+                len += replicated_data.len();
+                len += payload_data.len();
+            }
+            Payload::CompressedPayload {
+                sub_payload_data, ..
+            } => {
+                len += 1;
+                for sub_payload in sub_payload_data.iter() {
+                    len += 1;
+                    len += sub_payload.len();
+                }
+            }
+        }
+        len
+    }
+
+    pub fn stream_flags(&self) -> &StreamFlags {
+        match self {
+            Payload::NormalPayload { stream_flags, .. } => stream_flags,
+            Payload::CompressedPayload { stream_flags, .. } => stream_flags,
+        }
+    }
+
+    pub fn stream_flags_mut(&mut self) -> &mut StreamFlags {
+        match self {
+            Payload::NormalPayload { stream_flags, .. } => stream_flags,
+            Payload::CompressedPayload { stream_flags, .. } => stream_flags,
+        }
+    }
+
+    pub fn media_object_number(&self) -> u32 {
+        match self {
+            Payload::NormalPayload {
+                media_object_number,
+                ..
+            } => *media_object_number,
+            Payload::CompressedPayload {
+                media_object_number,
+                ..
+            } => *media_object_number,
+        }
+    }
+
+    /// The presentation time of the media object in milliseconds, if known.
+    /// For normal payloads, this is read from the replicated data.
+    pub fn presentation_time(&self) -> Option<u32> {
+        match self {
+            Payload::NormalPayload {
+                replicated_data, ..
+            } if replicated_data.len() >= 8 => Some(u32::from_le_bytes(
+                replicated_data[4..8].try_into().unwrap(),
+            )),
+            Payload::NormalPayload { .. } => None,
+            Payload::CompressedPayload {
+                presentation_time, ..
+            } => Some(*presentation_time),
+        }
+    }
+
+    /// Sets the presentation time of the media object in milliseconds, where
+    /// `presentation_time` finds it.
+    pub fn set_presentation_time(&mut self, time: u32) {
+        match self {
+            Payload::NormalPayload {
+                replicated_data, ..
+            } if replicated_data.len() >= 8 => {
+                replicated_data.to_mut()[4..8].copy_from_slice(&time.to_le_bytes())
+            }
+            Payload::NormalPayload { .. } => {}
+            Payload::CompressedPayload {
+                presentation_time, ..
+            } => *presentation_time = time,
+        }
+    }
+
+    /// Whether this payload starts a media object.
+    pub fn is_object_start(&self) -> bool {
+        match self {
+            Payload::NormalPayload {
+                offset_into_media_object,
+                ..
+            } => *offset_into_media_object == 0,
+            Payload::CompressedPayload { .. } => true,
+        }
+    }
+}
+
+/// Runs a parser over bytes that are all there, such as the payloads cut from
+/// a packet. Running out of input then means a length overran them, so it is
+/// reported where the parser started rather than as a need for more input.
+fn within<'a, O, E, F>(mut f: F) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, O, E>
+where
+    E: ParseError<Span<'a>>,
+    F: FnMut(Span<'a>) -> IResult<Span<'a>, O, E>,
+{
+    move |input: Span<'a>| match f(input) {
+        Err(Err::Incomplete(needed)) => {
+            let needed = match needed {
+                nom::Needed::Size(needed) => needed.get(),
+                nom::Needed::Unknown => 1,
+            };
+            let expected = input.len().saturating_add(needed) as u64;
+            Err(Err::Error(E::from_size_mismatch(
+                input,
+                expected,
+                input.len() as u64,
+            )))
+        }
+        result => result,
+    }
 }
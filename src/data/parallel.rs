@@ -0,0 +1,80 @@
+//! Parallel parsing of fixed-size packets, enabled by the `rayon` feature.
+
+use super::packets::DataPacket;
+use crate::{options::Budget, span::Span};
+use nom::Slice;
+use rayon::prelude::*;
+use std::convert::TryInto;
+
+/// Parses `count` packets of `packet_len` bytes each from `data`, in
+/// parallel and in order. Returns `None` if the packets do not fill `data`
+/// exactly or any of them fails to parse, leaving the caller to parse them
+/// one by one and report the error.
+pub(crate) fn parse_packets<'a>(
+    data: Span<'a>,
+    packet_len: u64,
+    count: u64,
+    budget: &Budget,
+) -> Option<Vec<DataPacket<'a>>> {
+    if packet_len == 0 || packet_len.checked_mul(count)? != data.len() as u64 {
+        return None;
+    }
+    let packet_len: usize = packet_len.try_into().ok()?;
+    let allocated = budget.allocated();
+    let packets = (0..data.len() / packet_len)
+        .into_par_iter()
+        .map(|i| {
+            let packet = data.slice(i * packet_len..(i + 1) * packet_len);
+            match DataPacket::parse_with::<nom::error::Error<_>>(packet, packet_len as u64, budget)
+            {
+                Ok((rest, packet)) if rest.is_empty() => Some(packet),
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<_>>>();
+    if packets.is_none() {
+        budget.restore(allocated);
+    }
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{guid::DATA_OBJECT, test_support::find_guid};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../../samples/basic.wmv");
+    const PACKET_LEN: usize = 1289;
+
+    fn packet_data(buf: &[u8]) -> &[u8] {
+        let data = find_guid(buf, DATA_OBJECT);
+        &buf[data + 50..data + 50 + PACKET_LEN * 138]
+    }
+
+    #[test]
+    fn parallel_packets() {
+        let data = Span::new(packet_data(BASIC_WMV));
+        let packets = parse_packets(data, PACKET_LEN as u64, 138, &Budget::default())
+            .expect("to parse successfully");
+        let mut parser = DataPacket::parser::<nom::error::Error<_>>(PACKET_LEN as u64);
+        let mut input = data;
+        for packet in packets.iter() {
+            let (rest, expected) = parser(input).expect("to parse successfully");
+            assert_eq!(packet, &expected);
+            input = rest;
+        }
+        assert_eq!(packets.len(), 138);
+    }
+
+    #[test]
+    fn parallel_fallback() {
+        let mut buf = packet_data(BASIC_WMV).to_vec();
+        buf[PACKET_LEN * 5..PACKET_LEN * 5 + 16].fill(0xff);
+        let budget = Budget::default();
+        assert!(parse_packets(Span::new(&buf), PACKET_LEN as u64, 138, &budget).is_none());
+        assert_eq!(budget.allocated(), 0);
+
+        let data = Span::new(packet_data(BASIC_WMV));
+        assert!(parse_packets(data, PACKET_LEN as u64, 137, &budget).is_none());
+    }
+}
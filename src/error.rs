@@ -1,3 +1,342 @@
-use nom::error::VerboseError;
+use crate::{guid::object_name, span::Span};
+use nom::{
+    error::{ContextError, ErrorKind, VerboseError},
+    Err, ErrorConvert,
+};
+use std::fmt;
+use uuid::Uuid;
 
-pub type Error<I> = VerboseError<I>;
+/// The parse error trait used throughout the crate. On top of nom's own
+/// traits, it lets parsers say which entry of a list failed and report
+/// GUID and size problems with the values involved.
+pub trait ParseError<I>: nom::error::ParseError<I> + ContextError<I> {
+    /// The error type of bit-level parsers over the same input.
+    type Bits: BitParseError<I> + ErrorConvert<Self>;
+
+    /// Records that the error happened in the `index`th entry of a list.
+    fn add_entry(_input: I, _name: &'static str, _index: usize, other: Self) -> Self {
+        other
+    }
+
+    fn from_bad_guid(input: I, _expected: Uuid) -> Self {
+        Self::from_error_kind(input, ErrorKind::Tag)
+    }
+
+    /// A size field of `actual` bytes, smaller than the `expected` minimum.
+    fn from_size_mismatch(input: I, _expected: u64, _actual: u64) -> Self {
+        Self::from_error_kind(input, ErrorKind::Verify)
+    }
+}
+
+/// The error trait for parsers reading bit fields, whose input carries a bit
+/// offset alongside the bytes.
+pub trait BitParseError<I>: nom::error::ParseError<(I, usize)> + ContextError<(I, usize)> {}
+
+impl<I, E> BitParseError<I> for E where
+    E: nom::error::ParseError<(I, usize)> + ContextError<(I, usize)>
+{
+}
+
+impl<I> ParseError<I> for nom::error::Error<I> {
+    type Bits = nom::error::Error<(I, usize)>;
+}
+
+impl<I> ParseError<I> for VerboseError<I> {
+    type Bits = VerboseError<(I, usize)>;
+}
+
+/// Adds the object with the given GUID to the path of a failed parse.
+pub(crate) fn in_object<'a, E: ParseError<Span<'a>>>(
+    input: Span<'a>,
+    guid: Uuid,
+) -> impl FnOnce(Err<E>) -> Err<E> + 'a {
+    move |err| err.map(|error| E::add_context(input, object_name(&guid), error))
+}
+
+/// One step in the chain of objects enclosing an error.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathElement {
+    Object(&'static str),
+    Entry(&'static str, usize),
+}
+
+impl fmt::Display for PathElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathElement::Object(name) => write!(f, "{}", name),
+            PathElement::Entry(name, index) => write!(f, "{} {}", name, index),
+        }
+    }
+}
+
+/// An error from parsing an ASF file. Offsets are absolute, and the path lists
+/// the enclosing objects from the outermost in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AsfError {
+    BadGuid {
+        offset: usize,
+        path: Vec<PathElement>,
+        expected: Option<Uuid>,
+        found: Option<Uuid>,
+    },
+    SizeMismatch {
+        offset: usize,
+        path: Vec<PathElement>,
+        expected: Option<u64>,
+        actual: Option<u64>,
+    },
+    Truncated {
+        offset: usize,
+        path: Vec<PathElement>,
+    },
+    InvalidValue {
+        offset: usize,
+        path: Vec<PathElement>,
+    },
+    LimitExceeded {
+        offset: usize,
+        path: Vec<PathElement>,
+    },
+}
+
+impl AsfError {
+    pub fn offset(&self) -> usize {
+        match self {
+            AsfError::BadGuid { offset, .. }
+            | AsfError::SizeMismatch { offset, .. }
+            | AsfError::Truncated { offset, .. }
+            | AsfError::InvalidValue { offset, .. }
+            | AsfError::LimitExceeded { offset, .. } => *offset,
+        }
+    }
+
+    pub fn path(&self) -> &[PathElement] {
+        match self {
+            AsfError::BadGuid { path, .. }
+            | AsfError::SizeMismatch { path, .. }
+            | AsfError::Truncated { path, .. }
+            | AsfError::InvalidValue { path, .. }
+            | AsfError::LimitExceeded { path, .. } => path,
+        }
+    }
+
+    /// Moves the error by `base` bytes, for input that did not start at the
+    /// beginning of the file.
+    pub(crate) fn offset_by(mut self, base: usize) -> Self {
+        match &mut self {
+            AsfError::BadGuid { offset, .. }
+            | AsfError::SizeMismatch { offset, .. }
+            | AsfError::Truncated { offset, .. }
+            | AsfError::InvalidValue { offset, .. }
+            | AsfError::LimitExceeded { offset, .. } => *offset += base,
+        }
+        self
+    }
+
+    fn path_mut(&mut self) -> &mut Vec<PathElement> {
+        match self {
+            AsfError::BadGuid { path, .. }
+            | AsfError::SizeMismatch { path, .. }
+            | AsfError::Truncated { path, .. }
+            | AsfError::InvalidValue { path, .. }
+            | AsfError::LimitExceeded { path, .. } => path,
+        }
+    }
+
+    /// Unwraps a nom error, treating running out of input as truncation at
+    /// the end of `input`.
+    pub(crate) fn from_err(err: Err<AsfError>, input: Span) -> Self {
+        match err {
+            Err::Error(error) | Err::Failure(error) => error,
+            Err::Incomplete(_) => AsfError::Truncated {
+                offset: input.location_offset() + input.len(),
+                path: Vec::new(),
+            },
+        }
+    }
+
+    fn from_span_kind(input: Span, kind: ErrorKind) -> Self {
+        let offset = input.location_offset();
+        let path = Vec::new();
+        match kind {
+            ErrorKind::Tag => AsfError::BadGuid {
+                offset,
+                path,
+                expected: None,
+                found: crate::guid::guid::<_, ()>(input)
+                    .ok()
+                    .map(|(_, found)| found),
+            },
+            ErrorKind::Verify => AsfError::SizeMismatch {
+                offset,
+                path,
+                expected: None,
+                actual: None,
+            },
+            ErrorKind::Complete => AsfError::Truncated { offset, path },
+            ErrorKind::TooLarge => AsfError::LimitExceeded { offset, path },
+            _ => AsfError::InvalidValue { offset, path },
+        }
+    }
+}
+
+impl fmt::Display for AsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsfError::BadGuid {
+                expected, found, ..
+            } => {
+                write!(f, "unexpected GUID")?;
+                if let Some(found) = found {
+                    write!(f, " {}", found)?;
+                }
+                if let Some(expected) = expected {
+                    write!(f, " (expected {})", expected)?;
+                }
+            }
+            AsfError::SizeMismatch {
+                expected, actual, ..
+            } => {
+                write!(f, "bad size")?;
+                if let (Some(expected), Some(actual)) = (expected, actual) {
+                    write!(f, " {} (expected at least {})", actual, expected)?;
+                }
+            }
+            AsfError::Truncated { .. } => write!(f, "unexpected end of data")?,
+            AsfError::InvalidValue { .. } => write!(f, "invalid value")?,
+            AsfError::LimitExceeded { .. } => write!(f, "parse limit exceeded")?,
+        }
+        write!(f, " at offset {:#x}", self.offset())?;
+        for (i, element) in self.path().iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " in " } else { " > " }, element)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AsfError {}
+
+impl<'a> nom::error::ParseError<Span<'a>> for AsfError {
+    fn from_error_kind(input: Span<'a>, kind: ErrorKind) -> Self {
+        Self::from_span_kind(input, kind)
+    }
+
+    fn append(_input: Span<'a>, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> ContextError<Span<'a>> for AsfError {
+    fn add_context(_input: Span<'a>, ctx: &'static str, mut other: Self) -> Self {
+        other.path_mut().insert(0, PathElement::Object(ctx));
+        other
+    }
+}
+
+impl<'a> ParseError<Span<'a>> for AsfError {
+    type Bits = AsfError;
+
+    fn add_entry(_input: Span<'a>, name: &'static str, index: usize, mut other: Self) -> Self {
+        other.path_mut().insert(0, PathElement::Entry(name, index));
+        other
+    }
+
+    fn from_bad_guid(input: Span<'a>, expected: Uuid) -> Self {
+        match Self::from_span_kind(input, ErrorKind::Tag) {
+            AsfError::BadGuid {
+                offset,
+                path,
+                found,
+                ..
+            } => AsfError::BadGuid {
+                offset,
+                path,
+                expected: Some(expected),
+                found,
+            },
+            error => error,
+        }
+    }
+
+    fn from_size_mismatch(input: Span<'a>, expected: u64, actual: u64) -> Self {
+        AsfError::SizeMismatch {
+            offset: input.location_offset(),
+            path: Vec::new(),
+            expected: Some(expected),
+            actual: Some(actual),
+        }
+    }
+}
+
+// Bit-level parsers report the byte they were reading.
+impl<'a> nom::error::ParseError<(Span<'a>, usize)> for AsfError {
+    fn from_error_kind(input: (Span<'a>, usize), kind: ErrorKind) -> Self {
+        Self::from_span_kind(input.0, kind)
+    }
+
+    fn append(_input: (Span<'a>, usize), _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> ContextError<(Span<'a>, usize)> for AsfError {
+    fn add_context(input: (Span<'a>, usize), ctx: &'static str, other: Self) -> Self {
+        <Self as ContextError<Span<'a>>>::add_context(input.0, ctx, other)
+    }
+}
+
+impl ErrorConvert<AsfError> for AsfError {
+    fn convert(self) -> AsfError {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{guid::*, test_support::find_guid};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[test]
+    fn error_paths() {
+        // Overwrite the header of the third packet.
+        let data = find_guid(BASIC_WMV, DATA_OBJECT);
+        let packet = data + 50 + 1289 * 2;
+        let mut buf = BASIC_WMV.to_vec();
+        buf[packet..packet + 16].fill(0xff);
+        let error = crate::parse(&buf).expect_err("expected a bad packet to fail");
+        // The packet length field after the error correction data and flags
+        // now claims far more than the data object holds.
+        assert_eq!(
+            error,
+            AsfError::SizeMismatch {
+                offset: packet + 3 + 2,
+                path: vec![PathElement::Object("data"), PathElement::Entry("packet", 2)],
+                expected: None,
+                actual: None,
+            }
+        );
+
+        // Shrink the file properties object below its header size.
+        let props = find_guid(BASIC_WMV, FILE_PROPERTIES_OBJECT);
+        let mut buf = BASIC_WMV.to_vec();
+        buf[props + 16..props + 24].copy_from_slice(&8u64.to_le_bytes());
+        let error = crate::parse(&buf).expect_err("expected a bad size to fail");
+        assert_eq!(
+            error,
+            AsfError::SizeMismatch {
+                offset: props + 16,
+                path: vec![
+                    PathElement::Object("header"),
+                    PathElement::Object("file properties")
+                ],
+                expected: Some(24),
+                actual: Some(8),
+            }
+        );
+        assert!(error
+            .to_string()
+            .starts_with("bad size 8 (expected at least 24)"));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{
+    data::DataObject,
+    guid::*,
+    header::{file_properties::*, HeaderObjects},
+    index::{
+        simple_index::{SimpleIndexData, SimpleIndexEntry},
+        IndexObject,
+    },
+    Container,
+};
+use std::{collections::HashMap, convert::TryInto};
+
+/// The index entry time interval used for generated simple indices, in
+/// 100-nanosecond units.
+pub const SIMPLE_INDEX_INTERVAL: u64 = 10_000_000;
+
+/// Turns a broadcast (live capture) container into a seekable file.
+///
+/// The packet counts and durations are recalculated from the packets, the
+/// broadcast flag is replaced by the seekable flag, and a simple index is
+/// built for every video stream. The file size is updated last, so it matches
+/// what `Container::write` will produce.
+pub fn finalize(container: &mut Container) -> Result<(), Box<dyn std::error::Error>> {
+    let preroll = container
+        .header
+        .file_properties()
+        .ok_or("missing file properties object")?
+        .preroll;
+    let durations = durations(&container.data, preroll)?;
+    update(container, durations)
+}
+
+/// Brings a file whose packets were rebuilt up to date, like `finalize`, but
+/// keeps the durations in its file properties. Only a broadcast, whose
+/// durations are placeholders, has them estimated from the packets.
+pub fn refresh(container: &mut Container) -> Result<(), Box<dyn std::error::Error>> {
+    let props = container
+        .header
+        .file_properties()
+        .ok_or("missing file properties object")?;
+    let durations = match props.is_broadcast() {
+        true => durations(&container.data, props.preroll)?,
+        false => (props.play_duration, props.send_duration),
+    };
+    update(container, durations)
+}
+
+fn update(
+    container: &mut Container,
+    (play_duration, send_duration): (u64, u64),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let total_data_packets: u64 = container.data.packets.len().try_into()?;
+    container.data.total_data_packets = total_data_packets;
+
+    let props = container
+        .header
+        .file_properties_mut()
+        .ok_or("missing file properties object")?;
+    props.data_packets_count = total_data_packets;
+    props.play_duration = play_duration;
+    props.send_duration = send_duration;
+    props.flags = (props.flags & !BROADCAST_FLAG) | SEEKABLE_FLAG;
+
+    let indices = build_simple_indices(&container.header, &container.data)?;
+    container
+        .indices
+        .objects
+        .retain(|object| !matches!(object, IndexObject::SimpleIndex(_)));
+    container
+        .indices
+        .objects
+        .extend(indices.into_iter().map(IndexObject::SimpleIndex));
+
+    let file_size: u64 = container.size_of().try_into()?;
+    if let Some(props) = container.header.file_properties_mut() {
+        props.file_size = file_size;
+    }
+    Ok(())
+}
+
+/// Calculates the play and send durations of the packets, in 100-nanosecond
+/// units. The play duration includes the preroll, like the one stored in the
+/// file properties object. Fails if they don't fit in 64 bits, which only
+/// happens with a corrupt preroll.
+pub fn durations(
+    data: &DataObject,
+    preroll: u64,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let mut send_end = 0u64;
+    let mut presentation = HashMap::<u8, (u32, u32)>::new();
+    for packet in data.packets.iter() {
+        let ppd = &packet.payload_parsing_data;
+        send_end = send_end.max(u64::from(ppd.send_time) + u64::from(ppd.duration));
+        for payload in packet.payload.payloads() {
+            if let Some(time) = payload.presentation_time() {
+                let times = presentation
+                    .entry(payload.stream_flags().stream_number)
+                    .or_insert((time, time));
+                if time > times.1 {
+                    *times = (times.1, time);
+                }
+            }
+        }
+    }
+
+    // The duration of the last media object isn't stored anywhere, so assume
+    // it is as long as the one before it.
+    let play_end = presentation
+        .values()
+        .map(|&(previous, last)| u64::from(last) + u64::from(last.saturating_sub(previous)))
+        .max()
+        .unwrap_or(preroll);
+
+    let play_duration = play_end
+        .checked_mul(10_000)
+        .ok_or("play duration is out of range")?;
+    let send_duration = send_end
+        .checked_mul(10_000)
+        .ok_or("send duration is out of range")?;
+    Ok((play_duration, send_duration))
+}
+
+/// Builds a simple index for each video stream in the file.
+pub fn build_simple_indices(
+    header: &HeaderObjects,
+    data: &DataObject,
+) -> Result<Vec<SimpleIndexData>, Box<dyn std::error::Error>> {
+    let props = header
+        .file_properties()
+        .ok_or("missing file properties object")?;
+    header
+        .stream_properties()
+        .filter(|stream| stream.stream_type == VIDEO_MEDIA)
+        .map(|stream| {
+            build_simple_index(
+                data,
+                stream.stream_number().try_into()?,
+                props.file_id,
+                props.play_duration,
+            )
+        })
+        .collect()
+}
+
+/// Builds a simple index for one stream, with an entry every
+/// `SIMPLE_INDEX_INTERVAL` up to `play_duration`. Each entry points to the
+/// packets holding the last key frame presented at or before its time.
+pub fn build_simple_index(
+    data: &DataObject,
+    stream_number: u8,
+    file_id: uuid::Uuid,
+    play_duration: u64,
+) -> Result<SimpleIndexData, Box<dyn std::error::Error>> {
+    // Packets spanned by each media object of the stream, and the presentation
+    // time and object of each key frame.
+    let mut objects: Vec<(usize, usize)> = Vec::new();
+    let mut key_frames = Vec::new();
+    for (packet_number, packet) in data.packets.iter().enumerate() {
+        for payload in packet.payload.payloads() {
+            let flags = payload.stream_flags();
+            if flags.stream_number != stream_number {
+                continue;
+            }
+            if payload.is_object_start() {
+                objects.push((packet_number, packet_number));
+                if let (true, Some(time)) = (flags.key_frame, payload.presentation_time()) {
+                    key_frames.push((time, objects.len() - 1));
+                }
+            } else if let Some(object) = objects.last_mut() {
+                object.1 = packet_number;
+            }
+        }
+    }
+    key_frames.sort_by_key(|&(time, object)| (time, object));
+
+    let mut index_entries = Vec::new();
+    if let Some(&first) = key_frames.first() {
+        for i in 0..=play_duration / SIMPLE_INDEX_INTERVAL {
+            let time = i * SIMPLE_INDEX_INTERVAL / 10_000;
+            let (_, object) = key_frames
+                .iter()
+                .take_while(|&&(t, _)| u64::from(t) <= time)
+                .last()
+                .copied()
+                .unwrap_or(first);
+            let (packet_number, last_packet_number) = objects[object];
+            let packet_count = last_packet_number - packet_number + 1;
+            index_entries.push(SimpleIndexEntry {
+                packet_number: packet_number.try_into()?,
+                packet_count: packet_count.try_into()?,
+            });
+        }
+    }
+
+    Ok(SimpleIndexData {
+        file_id,
+        index_entry_time_interval: SIMPLE_INDEX_INTERVAL,
+        maximum_packet_count: index_entries
+            .iter()
+            .map(|entry| u32::from(entry.packet_count))
+            .max()
+            .unwrap_or(0),
+        index_entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::AsfError, span::Span, test_support::find_guid};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[test]
+    fn finalize_broadcast_wmv() {
+        let (_, original) =
+            Container::parse::<AsfError>(Span::new(BASIC_WMV)).expect("to parse successfully");
+
+        // Turn the sample into a live capture without sizes, counts or an index.
+        let mut buf = BASIC_WMV.to_vec();
+        let props = find_guid(&buf, FILE_PROPERTIES_OBJECT) + 24;
+        buf[props + 16..props + 56].fill(0);
+        buf[props + 64] = BROADCAST_FLAG as u8;
+        let data = find_guid(&buf, DATA_OBJECT);
+        buf[data + 16..data + 24].fill(0);
+        buf[data + 40..data + 48].fill(0);
+        buf.truncate(find_guid(&buf, SIMPLE_INDEX_OBJECT));
+
+        let (_, mut container) =
+            Container::parse::<AsfError>(Span::new(&buf)).expect("to parse successfully");
+        assert!(container.indices.objects.is_empty());
+        finalize(&mut container).expect("finalize to succeed");
+
+        let expected = original.header.file_properties().unwrap();
+        let actual = container.header.file_properties().unwrap();
+        assert_eq!(actual.flags, SEEKABLE_FLAG);
+        assert_eq!(actual.data_packets_count, expected.data_packets_count);
+        assert_eq!(actual.send_duration, expected.send_duration);
+        // The duration of the last media object is estimated.
+        assert!(actual.play_duration.abs_diff(expected.play_duration) < SIMPLE_INDEX_INTERVAL);
+        assert_eq!(container.data.total_data_packets, 138);
+
+        let (index, expected_index) = match (
+            &container.indices.objects[..],
+            &original.indices.objects[..],
+        ) {
+            ([IndexObject::SimpleIndex(index)], [IndexObject::SimpleIndex(expected_index)]) => {
+                (index, expected_index)
+            }
+            _ => panic!("expected a single simple index"),
+        };
+        assert_eq!(index.file_id, expected_index.file_id);
+        assert_eq!(
+            index.maximum_packet_count,
+            expected_index.maximum_packet_count
+        );
+        assert_eq!(
+            index.index_entries[..],
+            expected_index.index_entries[..index.index_entries.len()]
+        );
+
+        let mut out = Vec::new();
+        container.write(&mut out).expect("write to succeed");
+        assert_eq!(out.len() as u64, actual.file_size);
+    }
+
+    #[test]
+    fn finalize_out_of_range_preroll() {
+        let mut container = crate::parse(BASIC_WMV).unwrap();
+        container.data.packets.clear();
+        container.header.file_properties_mut().unwrap().preroll = u64::MAX;
+        assert!(finalize(&mut container).is_err());
+    }
+}
@@ -79,6 +79,47 @@ pub const COMPATIBILITY_OBJECT: Uuid = Uuid::from_u128(0x75b22630668e11cfa6d900a
 pub const ADVANCED_CONTENT_ENCRYPTION_OBJECT: Uuid =
     Uuid::from_u128(0x43058533698149e69b74ad12cb86d58c);
 
+/// A readable name for an object GUID, used in error paths.
+pub fn object_name(guid: &Uuid) -> &'static str {
+    match *guid {
+        HEADER_OBJECT => "header",
+        DATA_OBJECT => "data",
+        SIMPLE_INDEX_OBJECT => "simple index",
+        INDEX_OBJECT => "index",
+        MEDIA_OBJECT_INDEX_OBJECT => "media object index",
+        TIMECODE_INDEX_OBJECT => "timecode index",
+        FILE_PROPERTIES_OBJECT => "file properties",
+        STREAM_PROPERTIES_OBJECT => "stream properties",
+        HEADER_EXTENSION_OBJECT => "header extension",
+        CODEC_LIST_OBJECT => "codec list",
+        SCRIPT_COMMAND_OBJECT => "script command",
+        MARKER_OBJECT => "marker",
+        BITRATE_MUTUAL_EXCLUSION_OBJECT => "bitrate mutual exclusion",
+        ERROR_CORRECTION_OBJECT => "error correction",
+        CONTENT_DESCRIPTION_OBJECT => "content description",
+        EXTENDED_CONTENT_DESCRIPTION_OBJECT => "extended content description",
+        CONTENT_BRANDING_OBJECT => "content branding",
+        STREAM_BITRATE_PROPERTIES_OBJECT => "stream bitrate properties",
+        CONTENT_ENCRYPTION_OBJECT => "content encryption",
+        EXTENDED_CONTENT_ENCRYPTION_OBJECT => "extended content encryption",
+        DIGITAL_SIGNATURE_OBJECT => "digital signature",
+        PADDING_OBJECT => "padding",
+        EXTENDED_STREAM_PROPERTIES_OBJECT => "extended stream properties",
+        ADVANCED_MUTUAL_EXCLUSION_OBJECT => "advanced mutual exclusion",
+        GROUP_MUTUAL_EXCLUSION_OBJECT => "group mutual exclusion",
+        STREAM_PRIORITIZATION_OBJECT => "stream prioritization",
+        BANDWIDTH_SHARING_OBJECT => "bandwidth sharing",
+        LANGUAGE_LIST_OBJECT => "language list",
+        METADATA_OBJECT => "metadata",
+        METADATA_LIBRARY_OBJECT => "metadata library",
+        INDEX_PARAMETERS_OBJECT => "index parameters",
+        MEDIA_OBJECT_INDEX_PARAMETERS_OBJECT => "media object index parameters",
+        TIMECODE_INDEX_PARAMETERS_OBJECT => "timecode index parameters",
+        ADVANCED_CONTENT_ENCRYPTION_OBJECT => "advanced content encryption",
+        _ => "unknown object",
+    }
+}
+
 pub const AUDIO_MEDIA: Uuid = Uuid::from_u128(0xf8699e405b4d11cfa8fd00805f5c442b);
 pub const VIDEO_MEDIA: Uuid = Uuid::from_u128(0xbc19efc05b4d11cfa8fd00805f5c442b);
 pub const COMMAND_MEDIA: Uuid = Uuid::from_u128(0x59dacfc059e611d0a3ac00a0c90348f6);
@@ -1,18 +1,17 @@
-use crate::{guid::*, span::Span};
+use crate::{combinators::span_bytes, error::ParseError, guid::*, span::Span};
 use nom::{
     bytes::streaming::take,
-    error::ParseError,
     multi::length_count,
     number::streaming::{le_u16, le_u32},
     IResult,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
 pub struct EncryptedObjectRecord<'a> {
     pub object_type: u16,
-    pub data: Span<'a>,
+    pub data: Cow<'a, [u8]>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -20,7 +19,7 @@ pub struct ContentEncryptionRecord<'a> {
     pub system_id: Uuid,
     pub system_version: u32,
     pub encrypted_object_records: Vec<EncryptedObjectRecord<'a>>,
-    pub data: Span<'a>,
+    pub data: Cow<'a, [u8]>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -32,12 +31,19 @@ impl<'a> EncryptedObjectRecord<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
         let (input, object_type) = le_u16(input)?;
         let (input, length) = le_u16(input)?;
-        let (input, data) = take(length)(input)?;
+        let (input, data) = span_bytes(take(length))(input)?;
         Ok((input, Self { object_type, data }))
     }
 
+    pub fn into_owned(self) -> EncryptedObjectRecord<'static> {
+        EncryptedObjectRecord {
+            object_type: self.object_type,
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+
     fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
-        let data_size: u32 = self.data.len().try_into()?;
+        let data_size: u16 = self.data.len().try_into()?;
         w.write_all(&self.object_type.to_le_bytes())?;
         w.write_all(&data_size.to_le_bytes())?;
         w.write_all(&self.data)?;
@@ -60,7 +66,7 @@ impl<'a> ContentEncryptionRecord<'a> {
         let (input, encrypted_object_records) =
             length_count(le_u16, EncryptedObjectRecord::parse)(input)?;
         let (input, data_size) = le_u32(input)?;
-        let (input, data) = take(data_size)(input)?;
+        let (input, data) = span_bytes(take(data_size))(input)?;
         Ok((
             input,
             Self {
@@ -72,6 +78,19 @@ impl<'a> ContentEncryptionRecord<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> ContentEncryptionRecord<'static> {
+        ContentEncryptionRecord {
+            system_id: self.system_id,
+            system_version: self.system_version,
+            encrypted_object_records: self
+                .encrypted_object_records
+                .into_iter()
+                .map(EncryptedObjectRecord::into_owned)
+                .collect(),
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+
     fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let encrypted_object_records_len: u16 = self.encrypted_object_records.len().try_into()?;
         let data_size: u32 = self.data.len().try_into()?;
@@ -112,6 +131,16 @@ impl<'a> AdvancedContentEncryptionData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> AdvancedContentEncryptionData<'static> {
+        AdvancedContentEncryptionData {
+            content_encryption_records: self
+                .content_encryption_records
+                .into_iter()
+                .map(ContentEncryptionRecord::into_owned)
+                .collect(),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let content_encryption_records_len: u16 =
             self.content_encryption_records.len().try_into()?;
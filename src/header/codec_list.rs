@@ -1,12 +1,11 @@
-use crate::{guid::*, span::Span, widestr::*};
+use crate::{combinators::span_bytes, error::ParseError, guid::*, span::Span, widestr::*};
 use nom::{
     bytes::streaming::take,
-    error::ParseError,
     multi::length_count,
     number::streaming::{le_u16, le_u32},
     IResult,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
@@ -14,7 +13,7 @@ pub struct CodecEntry<'a> {
     pub codec_type: u16,
     pub codec_name: WideStr,
     pub codec_description: WideStr,
-    pub codec_information: Span<'a>,
+    pub codec_information: Cow<'a, [u8]>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -29,7 +28,7 @@ impl<'a> CodecEntry<'a> {
         let (input, codec_name) = WideStr::parse_count16(input)?;
         let (input, codec_description) = WideStr::parse_count16(input)?;
         let (input, codec_information_len) = le_u16(input)?;
-        let (input, codec_information) = take(codec_information_len)(input)?;
+        let (input, codec_information) = span_bytes(take(codec_information_len))(input)?;
         Ok((
             input,
             Self {
@@ -41,6 +40,15 @@ impl<'a> CodecEntry<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> CodecEntry<'static> {
+        CodecEntry {
+            codec_type: self.codec_type,
+            codec_name: self.codec_name,
+            codec_description: self.codec_description,
+            codec_information: Cow::Owned(self.codec_information.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let codec_information_len: u16 = self.codec_information.len().try_into()?;
         w.write_all(&self.codec_type.to_le_bytes())?;
@@ -75,6 +83,17 @@ impl<'a> CodecListData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> CodecListData<'static> {
+        CodecListData {
+            reserved: self.reserved,
+            codec_entries: self
+                .codec_entries
+                .into_iter()
+                .map(CodecEntry::into_owned)
+                .collect(),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let codec_entries_len: u32 = self.codec_entries.len().try_into()?;
         w.write_all(&self.reserved.as_bytes_ms())?;
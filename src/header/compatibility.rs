@@ -1,5 +1,5 @@
-use crate::span::Span;
-use nom::{error::ParseError, number::streaming::le_u8, IResult};
+use crate::{error::ParseError, span::Span};
+use nom::{number::streaming::le_u8, IResult};
 use std::io::Write;
 
 #[derive(Debug, PartialEq)]
@@ -23,8 +23,8 @@ impl CompatibilityData {
 
     pub fn size_of(&self) -> usize {
         let mut len = 0;
-        len += 2;
-        len += 2;
+        len += 1;
+        len += 1;
         len
     }
 }
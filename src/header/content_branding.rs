@@ -1,24 +1,24 @@
-use crate::span::Span;
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u32, IResult};
-use std::{convert::TryInto, io::Write};
+use crate::{combinators::span_bytes, error::ParseError, span::Span};
+use nom::{bytes::streaming::take, number::streaming::le_u32, IResult};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
 pub struct ContentBrandingData<'a> {
     pub banner_image_type: u32,
-    pub banner_image_data: Span<'a>,
-    pub banner_image_url: Span<'a>,
-    pub copyright_url: Span<'a>,
+    pub banner_image_data: Cow<'a, [u8]>,
+    pub banner_image_url: Cow<'a, [u8]>,
+    pub copyright_url: Cow<'a, [u8]>,
 }
 
 impl<'a> ContentBrandingData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
         let (input, banner_image_type) = le_u32(input)?;
         let (input, banner_image_data_size) = le_u32(input)?;
-        let (input, banner_image_data) = take(banner_image_data_size)(input)?;
+        let (input, banner_image_data) = span_bytes(take(banner_image_data_size))(input)?;
         let (input, banner_image_url_length) = le_u32(input)?;
-        let (input, banner_image_url) = take(banner_image_url_length)(input)?;
+        let (input, banner_image_url) = span_bytes(take(banner_image_url_length))(input)?;
         let (input, copyright_url_length) = le_u32(input)?;
-        let (input, copyright_url) = take(copyright_url_length)(input)?;
+        let (input, copyright_url) = span_bytes(take(copyright_url_length))(input)?;
         Ok((
             input,
             Self {
@@ -30,10 +30,19 @@ impl<'a> ContentBrandingData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> ContentBrandingData<'static> {
+        ContentBrandingData {
+            banner_image_type: self.banner_image_type,
+            banner_image_data: Cow::Owned(self.banner_image_data.into_owned()),
+            banner_image_url: Cow::Owned(self.banner_image_url.into_owned()),
+            copyright_url: Cow::Owned(self.copyright_url.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
-        let banner_image_data_len: u16 = self.banner_image_data.len().try_into()?;
-        let banner_image_url_len: u16 = self.banner_image_url.len().try_into()?;
-        let copyright_url_len: u16 = self.copyright_url.len().try_into()?;
+        let banner_image_data_len: u32 = self.banner_image_data.len().try_into()?;
+        let banner_image_url_len: u32 = self.banner_image_url.len().try_into()?;
+        let copyright_url_len: u32 = self.copyright_url.len().try_into()?;
         w.write_all(&self.banner_image_type.to_le_bytes())?;
         w.write_all(&banner_image_data_len.to_le_bytes())?;
         w.write_all(&self.banner_image_data)?;
@@ -1,5 +1,5 @@
-use crate::{span::Span, widestr::*};
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u16, IResult};
+use crate::{error::ParseError, span::Span, widestr::*};
+use nom::{bytes::streaming::take, number::streaming::le_u16, IResult};
 use std::{convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
@@ -107,7 +107,7 @@ mod tests {
         }
     }
 
-    const BASIC_CONTENT_DESCRIPTOR_BYTES: &'static [u8] = &[
+    const BASIC_CONTENT_DESCRIPTOR_BYTES: &[u8] = &[
         0x33, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE,
         0x6C, 0x68, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2E, 0x00, 0x12, 0x00, 0x02, 0x00,
         0x02, 0x00, 0x02, 0x00, 0x54, 0x00, 0x68, 0x00, 0x65, 0x00, 0x20, 0x00, 0x4D, 0x00, 0x61,
@@ -147,7 +147,7 @@ mod tests {
         .write(&mut buf)
         .expect("write to succeed");
 
-        assert_eq!(buf.as_bytes(), &BASIC_CONTENT_DESCRIPTOR_BYTES[..])
+        assert_eq!(buf.as_bytes(), BASIC_CONTENT_DESCRIPTOR_BYTES)
     }
 
     #[test]
@@ -1,25 +1,25 @@
-use crate::span::Span;
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u32, IResult};
-use std::{convert::TryInto, io::Write};
+use crate::{combinators::span_bytes, error::ParseError, span::Span};
+use nom::{bytes::streaming::take, number::streaming::le_u32, IResult};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
 pub struct ContentEncryptionData<'a> {
-    pub secret_data: Span<'a>,
-    pub protection_type: Span<'a>,
-    pub key_id: Span<'a>,
-    pub license_url: Span<'a>,
+    pub secret_data: Cow<'a, [u8]>,
+    pub protection_type: Cow<'a, [u8]>,
+    pub key_id: Cow<'a, [u8]>,
+    pub license_url: Cow<'a, [u8]>,
 }
 
 impl<'a> ContentEncryptionData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
         let (input, secret_data_length) = le_u32(input)?;
-        let (input, secret_data) = take(secret_data_length)(input)?;
+        let (input, secret_data) = span_bytes(take(secret_data_length))(input)?;
         let (input, protection_type_length) = le_u32(input)?;
-        let (input, protection_type) = take(protection_type_length)(input)?;
+        let (input, protection_type) = span_bytes(take(protection_type_length))(input)?;
         let (input, key_id_length) = le_u32(input)?;
-        let (input, key_id) = take(key_id_length)(input)?;
+        let (input, key_id) = span_bytes(take(key_id_length))(input)?;
         let (input, license_url_length) = le_u32(input)?;
-        let (input, license_url) = take(license_url_length)(input)?;
+        let (input, license_url) = span_bytes(take(license_url_length))(input)?;
 
         Ok((
             input,
@@ -32,6 +32,15 @@ impl<'a> ContentEncryptionData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> ContentEncryptionData<'static> {
+        ContentEncryptionData {
+            secret_data: Cow::Owned(self.secret_data.into_owned()),
+            protection_type: Cow::Owned(self.protection_type.into_owned()),
+            key_id: Cow::Owned(self.key_id.into_owned()),
+            license_url: Cow::Owned(self.license_url.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let secret_data_len: u32 = self.secret_data.len().try_into()?;
         let protection_type_len: u32 = self.protection_type.len().try_into()?;
@@ -66,13 +75,13 @@ impl<'a> ContentEncryptionData<'a> {
 
 #[cfg(test)]
 mod tests {
-    use nom::{error::VerboseError, AsBytes, Slice};
+    use nom::{error::VerboseError, AsBytes};
 
     use crate::header::*;
 
     use super::*;
 
-    const BASIC_CONTENT_ENCRYPTION_BYTES: &'static [u8] = &[
+    const BASIC_CONTENT_ENCRYPTION_BYTES: &[u8] = &[
         0xFB, 0xB3, 0x11, 0x22, 0x23, 0xBD, 0xD2, 0x11, 0xB4, 0xB7, 0x00, 0xA0, 0xC9, 0x55, 0xFC,
         0x6E, 0xBC, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0xB8, 0xE8,
         0x9C, 0xBB, 0x79, 0x31, 0x80, 0x5C, 0x3D, 0x7F, 0xDD, 0x00, 0xC5, 0x5C, 0xE9, 0xBA, 0x80,
@@ -89,12 +98,11 @@ mod tests {
     ];
 
     fn basic_content_encryption_data() -> ContentEncryptionData<'static> {
-        let span = Span::new(BASIC_CONTENT_ENCRYPTION_BYTES);
         ContentEncryptionData {
-            secret_data: span.slice(28..52),
-            protection_type: span.slice(56..60),
-            key_id: span.slice(64..89),
-            license_url: span.slice(93..),
+            secret_data: Cow::Borrowed(&BASIC_CONTENT_ENCRYPTION_BYTES[28..52]),
+            protection_type: Cow::Borrowed(&BASIC_CONTENT_ENCRYPTION_BYTES[56..60]),
+            key_id: Cow::Borrowed(&BASIC_CONTENT_ENCRYPTION_BYTES[64..89]),
+            license_url: Cow::Borrowed(&BASIC_CONTENT_ENCRYPTION_BYTES[93..]),
         }
     }
 
@@ -116,7 +124,7 @@ mod tests {
             .write(&mut buf)
             .expect("write to succeed");
 
-        assert_eq!(buf.as_bytes(), &BASIC_CONTENT_ENCRYPTION_BYTES[..])
+        assert_eq!(buf.as_bytes(), BASIC_CONTENT_ENCRYPTION_BYTES)
     }
 
     #[test]
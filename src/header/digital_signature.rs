@@ -1,18 +1,18 @@
-use crate::span::Span;
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u32, IResult};
-use std::{convert::TryInto, io::Write};
+use crate::{combinators::span_bytes, error::ParseError, span::Span};
+use nom::{bytes::streaming::take, number::streaming::le_u32, IResult};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
 pub struct DigitalSignatureData<'a> {
     pub signature_type: u32,
-    pub signature_data: Span<'a>,
+    pub signature_data: Cow<'a, [u8]>,
 }
 
 impl<'a> DigitalSignatureData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
         let (input, signature_type) = le_u32(input)?;
         let (input, signature_data_size) = le_u32(input)?;
-        let (input, signature_data) = take(signature_data_size)(input)?;
+        let (input, signature_data) = span_bytes(take(signature_data_size))(input)?;
         Ok((
             input,
             DigitalSignatureData {
@@ -22,6 +22,13 @@ impl<'a> DigitalSignatureData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> DigitalSignatureData<'static> {
+        DigitalSignatureData {
+            signature_type: self.signature_type,
+            signature_data: Cow::Owned(self.signature_data.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let signature_data_len: u32 = self.signature_data.len().try_into()?;
         w.write_all(&self.signature_type.to_le_bytes())?;
@@ -1,19 +1,19 @@
-use crate::{guid::*, span::Span};
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u32, IResult};
-use std::{convert::TryInto, io::Write};
+use crate::{combinators::span_bytes, error::ParseError, guid::*, span::Span};
+use nom::{bytes::streaming::take, number::streaming::le_u32, IResult};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
 pub struct ErrorCorrectionData<'a> {
     pub error_correction_type: Uuid,
-    pub error_correction_data: Span<'a>,
+    pub error_correction_data: Cow<'a, [u8]>,
 }
 
 impl<'a> ErrorCorrectionData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
         let (input, error_correction_type) = guid(input)?;
         let (input, error_correction_data_length) = le_u32(input)?;
-        let (input, error_correction_data) = take(error_correction_data_length)(input)?;
+        let (input, error_correction_data) = span_bytes(take(error_correction_data_length))(input)?;
 
         Ok((
             input,
@@ -24,6 +24,13 @@ impl<'a> ErrorCorrectionData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> ErrorCorrectionData<'static> {
+        ErrorCorrectionData {
+            error_correction_type: self.error_correction_type,
+            error_correction_data: Cow::Owned(self.error_correction_data.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let error_correction_data_len: u32 = self.error_correction_data.len().try_into()?;
         w.write_all(&self.error_correction_type.as_bytes_ms())?;
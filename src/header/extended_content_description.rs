@@ -1,15 +1,18 @@
-use crate::{span::Span, widestr::*};
-use nom::{
-    bytes::streaming::take, error::ParseError, multi::length_count, number::streaming::le_u16,
-    IResult,
+use crate::{
+    combinators::{count, span_bytes},
+    error::ParseError,
+    options::Budget,
+    span::Span,
+    widestr::*,
 };
-use std::{convert::TryInto, io::Write};
+use nom::{bytes::streaming::take, number::streaming::le_u16, IResult};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
 pub struct ContentDescriptor<'a> {
     pub name: WideStr,
     pub value_type: u16,
-    pub value: Span<'a>,
+    pub value: Cow<'a, [u8]>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -19,23 +22,34 @@ pub struct ExtendedContentDescriptionData<'a> {
 
 impl<'a> ContentDescriptor<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
-        let (input, name) = WideStr::parse_count16(input)?;
+        let (input, name_len) = le_u16(input)?;
+        let (input, name) = take(name_len)(input)?;
         let (input, value_type) = le_u16(input)?;
         let (input, value_len) = le_u16(input)?;
-        let (input, value) = take(value_len)(input)?;
+        let (input, value) = span_bytes(take(value_len))(input)?;
         Ok((
             input,
             Self {
-                name,
+                name: WideStr::parse(name)?.1,
                 value_type,
                 value,
             },
         ))
     }
 
+    pub fn into_owned(self) -> ContentDescriptor<'static> {
+        ContentDescriptor {
+            name: self.name,
+            value_type: self.value_type,
+            value: Cow::Owned(self.value.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let name_len: u16 = self.name.size_of().try_into()?;
         let value_len: u16 = self.value.len().try_into()?;
-        self.name.write_count16(w)?;
+        w.write_all(&name_len.to_le_bytes())?;
+        self.name.write(w)?;
         w.write_all(&self.value_type.to_le_bytes())?;
         w.write_all(&value_len.to_le_bytes())?;
         w.write_all(&self.value)?;
@@ -44,7 +58,8 @@ impl<'a> ContentDescriptor<'a> {
 
     pub fn size_of(&self) -> usize {
         let mut len = 0;
-        len += self.name.size_of_count16();
+        len += 2;
+        len += self.name.size_of();
         len += 2;
         len += 2;
         len += self.value.len();
@@ -54,10 +69,34 @@ impl<'a> ContentDescriptor<'a> {
 
 impl<'a> ExtendedContentDescriptionData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
-        let (input, descriptors) = length_count(le_u16, ContentDescriptor::parse)(input)?;
+        Self::parse_with(input, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
+        let (input, descriptors_count) = le_u16(input)?;
+        budget.reserve::<ContentDescriptor, E>(
+            input,
+            descriptors_count.into(),
+            budget.limits.max_descriptors,
+        )?;
+        let (input, descriptors) =
+            count(ContentDescriptor::parse, descriptors_count.into())(input)?;
         Ok((input, Self { descriptors }))
     }
 
+    pub fn into_owned(self) -> ExtendedContentDescriptionData<'static> {
+        ExtendedContentDescriptionData {
+            descriptors: self
+                .descriptors
+                .into_iter()
+                .map(ContentDescriptor::into_owned)
+                .collect(),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let descriptors_len: u16 = self.descriptors.len().try_into()?;
         w.write_all(&descriptors_len.to_le_bytes())?;
@@ -1,19 +1,25 @@
-use crate::span::Span;
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u32, IResult};
-use std::{convert::TryInto, io::Write};
+use crate::{combinators::span_bytes, error::ParseError, span::Span};
+use nom::{bytes::streaming::take, number::streaming::le_u32, IResult};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
 pub struct ExtendedContentEncryptionData<'a> {
-    pub data: Span<'a>,
+    pub data: Cow<'a, [u8]>,
 }
 
 impl<'a> ExtendedContentEncryptionData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
         let (input, data_size) = le_u32(input)?;
-        let (input, data) = take(data_size)(input)?;
+        let (input, data) = span_bytes(take(data_size))(input)?;
         Ok((input, ExtendedContentEncryptionData { data }))
     }
 
+    pub fn into_owned(self) -> ExtendedContentEncryptionData<'static> {
+        ExtendedContentEncryptionData {
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let data_len: u32 = self.data.len().try_into()?;
         w.write_all(&data_len.to_le_bytes())?;
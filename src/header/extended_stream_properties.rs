@@ -1,14 +1,19 @@
 use super::stream_properties::*;
-use crate::{guid::*, object::*, span::Span, widestr::*};
+use crate::{
+    combinators::{count, span_bytes},
+    error::ParseError,
+    guid::*,
+    object::*,
+    span::Span,
+    widestr::*,
+};
 use nom::{
     bytes::streaming::take,
-    combinator::opt,
-    error::ParseError,
-    multi::count,
+    combinator::{complete, opt},
     number::streaming::{le_u16, le_u32, le_u64},
     IResult,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
@@ -21,7 +26,7 @@ pub struct StreamName {
 pub struct PayloadExtensionSystem<'a> {
     pub id: Uuid,
     pub data_size: u16,
-    pub info: Span<'a>,
+    pub info: Cow<'a, [u8]>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -80,7 +85,7 @@ impl<'a> PayloadExtensionSystem<'a> {
         let (input, id) = guid(input)?;
         let (input, data_size) = le_u16(input)?;
         let (input, info_length) = le_u32(input)?;
-        let (input, info) = take(info_length)(input)?;
+        let (input, info) = span_bytes(take(info_length))(input)?;
         Ok((
             input,
             PayloadExtensionSystem {
@@ -91,6 +96,14 @@ impl<'a> PayloadExtensionSystem<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> PayloadExtensionSystem<'static> {
+        PayloadExtensionSystem {
+            id: self.id,
+            data_size: self.data_size,
+            info: Cow::Owned(self.info.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let info_len: u32 = self.info.len().try_into()?;
         w.write_all(&self.id.as_bytes_ms())?;
@@ -132,7 +145,7 @@ impl<'a> ExtendedStreamPropertiesData<'a> {
             PayloadExtensionSystem::parse,
             payload_extension_system_count.into(),
         )(input)?;
-        let (input, stream_properties_object) = opt(object)(input)?;
+        let (input, stream_properties_object) = opt(complete(object))(input)?;
         Ok((
             input,
             ExtendedStreamPropertiesData {
@@ -159,6 +172,33 @@ impl<'a> ExtendedStreamPropertiesData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> ExtendedStreamPropertiesData<'static> {
+        ExtendedStreamPropertiesData {
+            start_time: self.start_time,
+            end_time: self.end_time,
+            data_bitrate: self.data_bitrate,
+            buffer_size: self.buffer_size,
+            initial_buffer_fullness: self.initial_buffer_fullness,
+            alternate_data_bitrate: self.alternate_data_bitrate,
+            alternate_buffer_size: self.alternate_buffer_size,
+            alternate_initial_buffer_fullness: self.alternate_initial_buffer_fullness,
+            maximum_object_size: self.maximum_object_size,
+            flags: self.flags,
+            stream_number: self.stream_number,
+            stream_language_id_index: self.stream_language_id_index,
+            average_time_per_frame: self.average_time_per_frame,
+            stream_names: self.stream_names,
+            payload_extension_systems: self
+                .payload_extension_systems
+                .into_iter()
+                .map(PayloadExtensionSystem::into_owned)
+                .collect(),
+            stream_properties_object: self
+                .stream_properties_object
+                .map(StreamPropertiesData::into_owned),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let stream_name_count: u16 = self.stream_names.len().try_into()?;
         let payload_extension_system_count: u16 =
@@ -185,6 +225,10 @@ impl<'a> ExtendedStreamPropertiesData<'a> {
             payload_extension_system.write(w)?;
         }
         if let Some(stream_properties_object) = &self.stream_properties_object {
+            let stream_properties_object_len: u64 =
+                (24 + stream_properties_object.size_of()).try_into()?;
+            w.write_all(&STREAM_PROPERTIES_OBJECT.as_bytes_ms())?;
+            w.write_all(&stream_properties_object_len.to_le_bytes())?;
             stream_properties_object.write(w)?;
         }
         Ok(())
@@ -214,6 +258,8 @@ impl<'a> ExtendedStreamPropertiesData<'a> {
             len += payload_extension_system.size_of();
         }
         if let Some(stream_properties_object) = &self.stream_properties_object {
+            len += 16;
+            len += 8;
             len += stream_properties_object.size_of();
         }
         len
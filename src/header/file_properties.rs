@@ -1,13 +1,20 @@
-use crate::{guid::*, span::Span};
+use crate::{error::ParseError, guid::*, span::Span};
 use nom::{
-    error::ParseError,
     number::streaming::{le_u32, le_u64},
     IResult,
 };
 use std::io::Write;
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq)]
+/// Set when the file is being (or was) written as a live broadcast. The file
+/// size, creation date, data packet count and durations are invalid.
+pub const BROADCAST_FLAG: u32 = 0x01;
+
+/// Set when the file can be seeked, i.e. it has a valid data object size and
+/// packet count, and packets of a fixed size.
+pub const SEEKABLE_FLAG: u32 = 0x02;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct FilePropertiesData {
     pub file_id: Uuid,
     pub file_size: u64,
@@ -54,6 +61,14 @@ impl FilePropertiesData {
         ))
     }
 
+    pub fn is_broadcast(&self) -> bool {
+        self.flags & BROADCAST_FLAG != 0
+    }
+
+    pub fn is_seekable(&self) -> bool {
+        self.flags & SEEKABLE_FLAG != 0
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         w.write_all(&self.file_id.as_bytes_ms())?;
         w.write_all(&self.file_size.to_le_bytes())?;
@@ -1,5 +1,5 @@
-use crate::{guid::*, span::Span};
-use nom::{error::ParseError, multi::length_count, number::streaming::le_u16, IResult};
+use crate::{error::ParseError, guid::*, span::Span};
+use nom::{multi::length_count, number::streaming::le_u16, IResult};
 use std::{convert::TryInto, io::Write};
 use uuid::Uuid;
 
@@ -9,16 +9,20 @@ use super::{
     stream_prioritization::StreamPrioritizationData,
     timecode_index_parameters::TimecodeIndexParametersData,
 };
-use crate::{guid::*, object::*, span::Span};
+use crate::{
+    error::{in_object, AsfError, ParseError},
+    guid::*,
+    object::*,
+    options::{limit_exceeded, Budget, ParseOptions},
+    span::Span,
+};
 use nom::{
     bytes::streaming::take,
     combinator::complete,
-    error::ParseError,
-    multi::many0,
     number::streaming::{le_u16, le_u32},
-    IResult,
+    Err, IResult, Slice,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
@@ -36,163 +40,309 @@ pub enum ExtensionHeaderObject<'a> {
     TimecodeIndexParameters(TimecodeIndexParametersData),
     Compatibility(CompatibilityData),
     AdvancedContentEncryption(AdvancedContentEncryptionData<'a>),
-    Unknown(Object<'a>),
+    Unknown {
+        guid: Uuid,
+        data: Cow<'a, [u8]>,
+    },
+    Invalid {
+        guid: Uuid,
+        data: Cow<'a, [u8]>,
+        error: AsfError,
+    },
 }
 
 impl<'a> ExtensionHeaderObject<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &ParseOptions::default(), &Budget::default())
+    }
+
+    /// Parses an object, keeping it as `Invalid` if its body is malformed.
+    pub fn parse_lenient<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        Self::parse_with(input, &options, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
         let (input, obj) = object(input)?;
+        let Object { guid, data } = obj;
+        if !options.lenient {
+            let body = Self::parse_body(obj, options, budget).map_err(in_object(data, guid));
+            return Ok((input, body?));
+        }
         Ok((
             input,
-            match obj {
-                Object {
-                    guid: EXTENDED_STREAM_PROPERTIES_OBJECT,
-                    data,
-                } => (Self::ExtendedStreamProperties(ExtendedStreamPropertiesData::parse(data)?.1)),
-                Object {
-                    guid: ADVANCED_MUTUAL_EXCLUSION_OBJECT,
-                    data,
-                } => (Self::AdvancedMutualExclusion(AdvancedMutualExclusionData::parse(data)?.1)),
-                Object {
-                    guid: GROUP_MUTUAL_EXCLUSION_OBJECT,
-                    data,
-                } => (Self::GroupMutualExclusion(GroupMutualExclusionData::parse(data)?.1)),
-                Object {
-                    guid: STREAM_PRIORITIZATION_OBJECT,
-                    data,
-                } => (Self::StreamPrioritization(StreamPrioritizationData::parse(data)?.1)),
-                Object {
-                    guid: BANDWIDTH_SHARING_OBJECT,
-                    data,
-                } => (Self::BandwidthSharing(BandwidthSharingData::parse(data)?.1)),
-                Object {
-                    guid: LANGUAGE_LIST_OBJECT,
-                    data,
-                } => (Self::LanguageList(LanguageListData::parse(data)?.1)),
-                Object {
-                    guid: METADATA_OBJECT,
-                    data,
-                } => (Self::Metadata(MetadataData::parse(data)?.1)),
-                Object {
-                    guid: METADATA_LIBRARY_OBJECT,
-                    data,
-                } => (Self::MetadataLibrary(MetadataLibraryData::parse(data)?.1)),
-                Object {
-                    guid: INDEX_PARAMETERS_OBJECT,
-                    data,
-                } => (Self::IndexParameters(IndexParametersData::parse(data)?.1)),
-                Object {
-                    guid: MEDIA_OBJECT_INDEX_PARAMETERS_OBJECT,
-                    data,
-                } => {
-                    Self::MediaObjectIndexParameters(MediaObjectIndexParametersData::parse(data)?.1)
+            match Self::parse_body::<AsfError>(obj, options, budget).map_err(in_object(data, guid))
+            {
+                Ok(obj) => obj,
+                // Only exceeded limits fail outright, and those aren't the
+                // object's fault.
+                Err(Err::Failure(AsfError::LimitExceeded { .. })) => {
+                    return Err(in_object(data, guid)(limit_exceeded(data)))
                 }
-                Object {
-                    guid: TIMECODE_INDEX_PARAMETERS_OBJECT,
-                    data,
-                } => (Self::TimecodeIndexParameters(TimecodeIndexParametersData::parse(data)?.1)),
-                Object {
-                    guid: COMPATIBILITY_OBJECT,
-                    data,
-                } => (Self::Compatibility(CompatibilityData::parse(data)?.1)),
-                Object {
-                    guid: ADVANCED_CONTENT_ENCRYPTION_OBJECT,
-                    data,
-                } => Self::AdvancedContentEncryption(AdvancedContentEncryptionData::parse(data)?.1),
-                unknown => Self::Unknown(unknown),
+                Err(error) => Self::Invalid {
+                    guid,
+                    data: Cow::Borrowed(*data.fragment()),
+                    error: AsfError::from_err(error, data),
+                },
             },
         ))
     }
 
+    fn parse_body<E: ParseError<Span<'a>>>(
+        obj: Object<'a>,
+        _options: &ParseOptions,
+        budget: &Budget,
+    ) -> Result<Self, Err<E>> {
+        Ok(match obj {
+            Object {
+                guid: EXTENDED_STREAM_PROPERTIES_OBJECT,
+                data,
+            } => Self::ExtendedStreamProperties(ExtendedStreamPropertiesData::parse(data)?.1),
+            Object {
+                guid: ADVANCED_MUTUAL_EXCLUSION_OBJECT,
+                data,
+            } => Self::AdvancedMutualExclusion(AdvancedMutualExclusionData::parse(data)?.1),
+            Object {
+                guid: GROUP_MUTUAL_EXCLUSION_OBJECT,
+                data,
+            } => Self::GroupMutualExclusion(GroupMutualExclusionData::parse(data)?.1),
+            Object {
+                guid: STREAM_PRIORITIZATION_OBJECT,
+                data,
+            } => Self::StreamPrioritization(StreamPrioritizationData::parse(data)?.1),
+            Object {
+                guid: BANDWIDTH_SHARING_OBJECT,
+                data,
+            } => Self::BandwidthSharing(BandwidthSharingData::parse(data)?.1),
+            Object {
+                guid: LANGUAGE_LIST_OBJECT,
+                data,
+            } => Self::LanguageList(LanguageListData::parse(data)?.1),
+            Object {
+                guid: METADATA_OBJECT,
+                data,
+            } => Self::Metadata(MetadataData::parse_with(data, budget)?.1),
+            Object {
+                guid: METADATA_LIBRARY_OBJECT,
+                data,
+            } => Self::MetadataLibrary(MetadataLibraryData::parse_with(data, budget)?.1),
+            Object {
+                guid: INDEX_PARAMETERS_OBJECT,
+                data,
+            } => Self::IndexParameters(IndexParametersData::parse(data)?.1),
+            Object {
+                guid: MEDIA_OBJECT_INDEX_PARAMETERS_OBJECT,
+                data,
+            } => Self::MediaObjectIndexParameters(MediaObjectIndexParametersData::parse(data)?.1),
+            Object {
+                guid: TIMECODE_INDEX_PARAMETERS_OBJECT,
+                data,
+            } => Self::TimecodeIndexParameters(TimecodeIndexParametersData::parse(data)?.1),
+            Object {
+                guid: COMPATIBILITY_OBJECT,
+                data,
+            } => Self::Compatibility(CompatibilityData::parse(data)?.1),
+            Object {
+                guid: ADVANCED_CONTENT_ENCRYPTION_OBJECT,
+                data,
+            } => Self::AdvancedContentEncryption(AdvancedContentEncryptionData::parse(data)?.1),
+            Object { guid, data } => Self::Unknown {
+                guid,
+                data: Cow::Borrowed(*data.fragment()),
+            },
+        })
+    }
+
     pub fn parse_many<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Vec<Self>, E> {
-        many0(complete(Self::parse))(input)
+        Self::parse_many_with(input, &ParseOptions::default(), &Budget::default())
+    }
+
+    pub(crate) fn parse_many_with<E: ParseError<Span<'a>>>(
+        mut input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Vec<Self>, E> {
+        let mut objects = Vec::new();
+        while !input.is_empty() {
+            let max = budget
+                .limits
+                .max_header_objects
+                .saturating_sub(objects.len());
+            budget.reserve::<Self, E>(input, 1, max)?;
+            let (rest, obj) =
+                match complete(|input| Self::parse_with(input, options, budget))(input) {
+                    Ok(result) => result,
+                    // Without a valid object size there is no way to find the
+                    // next object, so a lenient parse keeps the rest as one.
+                    Err(Err::Error(_)) if options.lenient => {
+                        let (guid, data, error) = unsized_object(input);
+                        let rest = input.slice(input.len()..);
+                        let obj = Self::Invalid {
+                            guid,
+                            data: Cow::Borrowed(*data.fragment()),
+                            error,
+                        };
+                        (rest, obj)
+                    }
+                    Err(error) => return Err(error),
+                };
+            objects.push(obj);
+            input = rest;
+        }
+        Ok((input, objects))
+    }
+
+    pub fn into_owned(self) -> ExtensionHeaderObject<'static> {
+        match self {
+            ExtensionHeaderObject::ExtendedStreamProperties(data) => {
+                ExtensionHeaderObject::ExtendedStreamProperties(data.into_owned())
+            }
+            ExtensionHeaderObject::Metadata(data) => {
+                ExtensionHeaderObject::Metadata(data.into_owned())
+            }
+            ExtensionHeaderObject::MetadataLibrary(data) => {
+                ExtensionHeaderObject::MetadataLibrary(data.into_owned())
+            }
+            ExtensionHeaderObject::AdvancedContentEncryption(data) => {
+                ExtensionHeaderObject::AdvancedContentEncryption(data.into_owned())
+            }
+            ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
+                ExtensionHeaderObject::AdvancedMutualExclusion(data)
+            }
+            ExtensionHeaderObject::GroupMutualExclusion(data) => {
+                ExtensionHeaderObject::GroupMutualExclusion(data)
+            }
+            ExtensionHeaderObject::StreamPrioritization(data) => {
+                ExtensionHeaderObject::StreamPrioritization(data)
+            }
+            ExtensionHeaderObject::BandwidthSharing(data) => {
+                ExtensionHeaderObject::BandwidthSharing(data)
+            }
+            ExtensionHeaderObject::LanguageList(data) => ExtensionHeaderObject::LanguageList(data),
+            ExtensionHeaderObject::IndexParameters(data) => {
+                ExtensionHeaderObject::IndexParameters(data)
+            }
+            ExtensionHeaderObject::MediaObjectIndexParameters(data) => {
+                ExtensionHeaderObject::MediaObjectIndexParameters(data)
+            }
+            ExtensionHeaderObject::TimecodeIndexParameters(data) => {
+                ExtensionHeaderObject::TimecodeIndexParameters(data)
+            }
+            ExtensionHeaderObject::Compatibility(data) => {
+                ExtensionHeaderObject::Compatibility(data)
+            }
+            ExtensionHeaderObject::Unknown { guid, data } => ExtensionHeaderObject::Unknown {
+                guid,
+                data: Cow::Owned(data.into_owned()),
+            },
+            ExtensionHeaderObject::Invalid { guid, data, error } => {
+                ExtensionHeaderObject::Invalid {
+                    guid,
+                    data: Cow::Owned(data.into_owned()),
+                    error,
+                }
+            }
+        }
     }
 
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         match self {
             ExtensionHeaderObject::ExtendedStreamProperties(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&EXTENDED_STREAM_PROPERTIES_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&ADVANCED_MUTUAL_EXCLUSION_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::GroupMutualExclusion(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&GROUP_MUTUAL_EXCLUSION_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::StreamPrioritization(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&STREAM_PRIORITIZATION_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::BandwidthSharing(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&BANDWIDTH_SHARING_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::LanguageList(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&LANGUAGE_LIST_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::Metadata(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&METADATA_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::MetadataLibrary(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&METADATA_LIBRARY_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::IndexParameters(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&INDEX_PARAMETERS_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::MediaObjectIndexParameters(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&MEDIA_OBJECT_INDEX_PARAMETERS_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::TimecodeIndexParameters(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&TIMECODE_INDEX_PARAMETERS_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::Compatibility(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&COMPATIBILITY_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
             ExtensionHeaderObject::AdvancedContentEncryption(data) => {
-                let data_len: u64 = data.size_of().try_into()?;
+                let data_len: u64 = self.size_of().try_into()?;
                 w.write_all(&ADVANCED_CONTENT_ENCRYPTION_OBJECT.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
                 data.write(w)?;
             }
-            ExtensionHeaderObject::Unknown(unk) => {
-                let data_len: u64 = unk.data.len().try_into()?;
-                w.write_all(&unk.guid.as_bytes_ms())?;
+            ExtensionHeaderObject::Unknown { guid, data } => {
+                let data_len: u64 = self.size_of().try_into()?;
+                w.write_all(&guid.as_bytes_ms())?;
+                w.write_all(&data_len.to_le_bytes())?;
+                w.write_all(data)?;
+            }
+            ExtensionHeaderObject::Invalid { guid, data, .. } => {
+                let data_len: u64 = self.size_of().try_into()?;
+                w.write_all(&guid.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
-                w.write_all(&unk.data)?;
+                w.write_all(data)?;
             }
         }
         Ok(())
@@ -216,7 +366,8 @@ impl<'a> ExtensionHeaderObject<'a> {
             ExtensionHeaderObject::TimecodeIndexParameters(data) => data.size_of(),
             ExtensionHeaderObject::Compatibility(data) => data.size_of(),
             ExtensionHeaderObject::AdvancedContentEncryption(data) => data.size_of(),
-            ExtensionHeaderObject::Unknown(unk) => unk.data.len(),
+            ExtensionHeaderObject::Unknown { data, .. } => data.len(),
+            ExtensionHeaderObject::Invalid { data, .. } => data.len(),
         };
         len
     }
@@ -224,27 +375,49 @@ impl<'a> ExtensionHeaderObject<'a> {
 
 #[derive(Debug, PartialEq)]
 pub struct HeaderExtensionData<'a> {
-    reserved_1: Uuid,
-    reserved_2: u16,
-    extension_objects: Vec<ExtensionHeaderObject<'a>>,
+    pub reserved_1: Uuid,
+    pub reserved_2: u16,
+    pub extension_objects: Vec<ExtensionHeaderObject<'a>>,
 }
 
 impl<'a> HeaderExtensionData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &ParseOptions::default(), &Budget::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
         let (input, reserved_1) = guid(input)?;
         let (input, reserved_2) = le_u16(input)?;
         let (input, extension_data_size) = le_u32(input)?;
         let (input, extension_data) = take(extension_data_size)(input)?;
+        let (_, extension_objects) =
+            ExtensionHeaderObject::parse_many_with(extension_data, options, budget)?;
         Ok((
             input,
             HeaderExtensionData {
                 reserved_1,
                 reserved_2,
-                extension_objects: ExtensionHeaderObject::parse_many(extension_data)?.1,
+                extension_objects,
             },
         ))
     }
 
+    pub fn into_owned(self) -> HeaderExtensionData<'static> {
+        HeaderExtensionData {
+            reserved_1: self.reserved_1,
+            reserved_2: self.reserved_2,
+            extension_objects: self
+                .extension_objects
+                .into_iter()
+                .map(ExtensionHeaderObject::into_owned)
+                .collect(),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let extension_data_size: u32 = self
             .extension_objects
@@ -1,7 +1,6 @@
-use crate::{span::Span, widestr::*};
+use crate::{error::ParseError, span::Span, widestr::*};
 use nom::{
     bytes::streaming::take,
-    error::ParseError,
     multi::length_count,
     number::streaming::{le_u16, le_u8},
     IResult,
@@ -14,6 +13,36 @@ pub struct LanguageListData {
 }
 
 impl LanguageListData {
+    /// The language tag at `index`, without its terminating nul.
+    pub fn language(&self, index: u16) -> Option<String> {
+        let record = self.language_id_records.get(usize::from(index))?;
+        Some(record.to_str().trim_end_matches('\0').to_string())
+    }
+
+    /// The index of `tag`, ignoring ASCII case.
+    pub fn index_of(&self, tag: &str) -> Option<u16> {
+        let index = self.language_id_records.iter().position(|record| {
+            let language = record.to_str();
+            language.trim_end_matches('\0').eq_ignore_ascii_case(tag)
+        })?;
+        index.try_into().ok()
+    }
+
+    /// The index of `tag`, adding it to the end of the list if it is not
+    /// there yet. Returns `None` if the list or the tag is too long to write.
+    pub fn add(&mut self, tag: &str) -> Option<u16> {
+        if let Some(index) = self.index_of(tag) {
+            return Some(index);
+        }
+        let index = self.language_id_records.len().try_into().ok()?;
+        let record = WideStr::new(&format!("{}\0", tag));
+        if record.size_of() > usize::from(u8::MAX) {
+            return None;
+        }
+        self.language_id_records.push(record);
+        Some(index)
+    }
+
     fn parse_id<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, WideStr, E> {
         let (input, length) = le_u8(input)?;
         let (input, data) = take(length)(input)?;
@@ -51,3 +80,98 @@ impl LanguageListData {
         len
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{
+        extended_stream_properties::StreamName,
+        header_extension::ExtensionHeaderObject,
+        metadata_library::{DescriptionRecord, MetadataLibraryData},
+        HeaderObject, HeaderObjects,
+    };
+    use std::borrow::Cow;
+
+    const BASIC_DRMV2_WMA: &[u8] = include_bytes!("../../samples/basic-drmv2.wma");
+
+    #[test]
+    fn resolve_languages() {
+        let mut header = crate::parse(BASIC_DRMV2_WMA).unwrap().header;
+        assert_eq!(header.language(0), Some("en-us".to_string()));
+        assert_eq!(header.language(1), None);
+        assert_eq!(header.language_index("EN-US"), Some(0));
+        assert_eq!(header.language_index("fr-fr"), None);
+        assert_eq!(header.add_language("EN-US"), Some(0));
+        assert_eq!(header.add_language("fr-fr"), Some(1));
+        assert_eq!(header.language_index("fr-fr"), Some(1));
+        assert_eq!(header.language(1), Some("fr-fr".to_string()));
+
+        let mut out = Vec::new();
+        header.write(&mut out).unwrap();
+        let (_, header) = HeaderObjects::parse::<nom::error::Error<_>>(Span::new(&out)).unwrap();
+        assert_eq!(header.language(1), Some("fr-fr".to_string()));
+    }
+
+    #[test]
+    fn add_language_list() {
+        let mut header = crate::parse(BASIC_DRMV2_WMA).unwrap().header;
+        header
+            .objects
+            .retain(|object| !matches!(object, HeaderObject::HeaderExtension(_)));
+        assert_eq!(header.language_list(), None);
+        assert_eq!(header.language_index("de-de"), None);
+        // Streams without extended stream properties get no language.
+        assert_eq!(header.set_stream_language(1, "de-de"), None);
+        assert_eq!(header.language_list(), None);
+        assert_eq!(header.add_language("de-de"), Some(0));
+        assert_eq!(header.language(0), Some("de-de".to_string()));
+    }
+
+    #[test]
+    fn set_record_languages() {
+        let mut header = crate::parse(BASIC_DRMV2_WMA).unwrap().header;
+        let extended = header
+            .extension_objects_mut()
+            .find_map(|object| match object {
+                ExtensionHeaderObject::ExtendedStreamProperties(data) => Some(data),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(extended.stream_number, 1);
+        extended.stream_names = vec![StreamName {
+            language_id_index: 0,
+            stream_name: WideStr::new("Audio"),
+        }];
+        header.header_extension_mut().extension_objects.push(
+            ExtensionHeaderObject::MetadataLibrary(MetadataLibraryData {
+                description_records: vec![DescriptionRecord {
+                    language_list_index: 0,
+                    stream_number: 1,
+                    data_type: 0,
+                    name: WideStr::new("Title"),
+                    data: Cow::Borrowed(&[0, 0]),
+                }],
+            }),
+        );
+
+        assert_eq!(header.set_stream_name_language(1, 1, "fr-fr"), None);
+        assert_eq!(header.set_metadata_library_language(1, "fr-fr"), None);
+        assert_eq!(header.language_index("fr-fr"), None);
+        assert_eq!(header.set_stream_name_language(1, 0, "fr-fr"), Some(()));
+        assert_eq!(header.set_metadata_library_language(0, "de-de"), Some(()));
+
+        let stream = &header.streams()[0];
+        assert_eq!(stream.names[0].0, Some("fr-fr".to_string()));
+        let library = header
+            .extension_objects()
+            .find_map(|object| match object {
+                ExtensionHeaderObject::MetadataLibrary(data) => Some(data),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            header.language(library.description_records[0].language_list_index),
+            Some("de-de".to_string())
+        );
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{guid::*, span::Span, widestr::*};
+use crate::{
+    combinators::count, error::ParseError, guid::*, options::Budget, span::Span, widestr::*,
+};
 use nom::{
-    error::ParseError,
-    multi::count,
     number::streaming::{le_u16, le_u32, le_u64},
     IResult,
 };
@@ -71,10 +71,18 @@ impl Marker {
 
 impl MarkerData {
     pub fn parse<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<'a, E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
         let (input, reserved_1) = guid(input)?;
         let (input, markers_count) = le_u32(input)?;
         let (input, reserved_2) = le_u16(input)?;
         let (input, name) = WideStr::parse_count16(input)?;
+        budget.reserve::<Marker, E>(input, markers_count as _, budget.limits.max_markers)?;
         let (input, markers) = count(Marker::parse, markers_count as _)(input)?;
         Ok((
             input,
@@ -106,7 +114,6 @@ impl MarkerData {
         len += 2;
         len += self.name.size_of_count16();
         for marker in self.markers.iter() {
-            len += 1;
             len += marker.size_of();
         }
         len
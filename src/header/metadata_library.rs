@@ -1,12 +1,16 @@
-use crate::{span::Span, widestr::*};
+use crate::{
+    combinators::{count, span_bytes},
+    error::ParseError,
+    options::Budget,
+    span::Span,
+    widestr::*,
+};
 use nom::{
     bytes::streaming::take,
-    error::ParseError,
-    multi::length_count,
     number::streaming::{le_u16, le_u32},
     IResult,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
 pub struct DescriptionRecord<'a> {
@@ -14,7 +18,7 @@ pub struct DescriptionRecord<'a> {
     pub stream_number: u16,
     pub data_type: u16,
     pub name: WideStr,
-    pub data: Span<'a>,
+    pub data: Cow<'a, [u8]>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,7 +34,7 @@ impl<'a> DescriptionRecord<'a> {
         let (input, data_type) = le_u16(input)?;
         let (input, data_length) = le_u32(input)?;
         let (input, name) = take(name_length)(input)?;
-        let (input, data) = take(data_length)(input)?;
+        let (input, data) = span_bytes(take(data_length))(input)?;
         Ok((
             input,
             DescriptionRecord {
@@ -43,9 +47,19 @@ impl<'a> DescriptionRecord<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> DescriptionRecord<'static> {
+        DescriptionRecord {
+            language_list_index: self.language_list_index,
+            stream_number: self.stream_number,
+            data_type: self.data_type,
+            name: self.name,
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let name_len: u16 = self.name.size_of().try_into()?;
-        let data_len: u16 = self.data.len().try_into()?;
+        let data_len: u32 = self.data.len().try_into()?;
         w.write_all(&self.language_list_index.to_le_bytes())?;
         w.write_all(&self.stream_number.to_le_bytes())?;
         w.write_all(&name_len.to_le_bytes())?;
@@ -71,7 +85,21 @@ impl<'a> DescriptionRecord<'a> {
 
 impl<'a> MetadataLibraryData<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
-        let (input, description_records) = length_count(le_u16, DescriptionRecord::parse)(input)?;
+        Self::parse_with(input, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
+        let (input, description_records_count) = le_u16(input)?;
+        budget.reserve::<DescriptionRecord, E>(
+            input,
+            description_records_count.into(),
+            budget.limits.max_descriptors,
+        )?;
+        let (input, description_records) =
+            count(DescriptionRecord::parse, description_records_count.into())(input)?;
         Ok((
             input,
             MetadataLibraryData {
@@ -80,6 +108,16 @@ impl<'a> MetadataLibraryData<'a> {
         ))
     }
 
+    pub fn into_owned(self) -> MetadataLibraryData<'static> {
+        MetadataLibraryData {
+            description_records: self
+                .description_records
+                .into_iter()
+                .map(DescriptionRecord::into_owned)
+                .collect(),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let description_records_len: u16 = self.description_records.len().try_into()?;
         w.write_all(&description_records_len.to_le_bytes())?;
@@ -28,26 +28,45 @@ pub mod stream_properties;
 pub mod timecode_index_parameters;
 
 use self::{
-    bitrate_mutual_exclusion::BitrateMutualExclusionData, codec_list::CodecListData,
-    content_branding::ContentBrandingData, content_description::ContentDescriptionData,
-    content_encryption::ContentEncryptionData, digital_signature::DigitalSignatureData,
+    bitrate_mutual_exclusion::BitrateMutualExclusionData,
+    codec_list::CodecListData,
+    content_branding::ContentBrandingData,
+    content_description::ContentDescriptionData,
+    content_encryption::ContentEncryptionData,
+    digital_signature::DigitalSignatureData,
     error_correction::ErrorCorrectionData,
     extended_content_description::ExtendedContentDescriptionData,
     extended_content_encryption::ExtendedContentEncryptionData,
-    file_properties::FilePropertiesData, header_extension::HeaderExtensionData, marker::MarkerData,
-    script_command::ScriptCommandData, stream_bitrate_properties::StreamBitratePropertiesData,
+    extended_stream_properties::ExtendedStreamPropertiesData,
+    file_properties::FilePropertiesData,
+    header_extension::{ExtensionHeaderObject, HeaderExtensionData},
+    language_list::LanguageListData,
+    marker::MarkerData,
+    metadata_library::MetadataLibraryData,
+    script_command::ScriptCommandData,
+    stream_bitrate_properties::StreamBitratePropertiesData,
     stream_properties::StreamPropertiesData,
 };
-use crate::{guid::*, object::*, span::Span};
+use crate::{
+    combinators::guid_tag,
+    error::{in_object, AsfError, ParseError},
+    guid::*,
+    object::*,
+    options::{limit_exceeded, Budget, ParseOptions},
+    select::{Constraints, Selection},
+    sniff::MediaType,
+    span::Span,
+    stream::Stream,
+};
 use nom::{
-    bytes::streaming::{tag, take},
+    bytes::streaming::take,
     combinator::complete,
-    error::ParseError,
-    multi::many0,
+    error::context,
     number::streaming::{le_u32, le_u64, le_u8},
-    IResult,
+    Err, IResult, Slice,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
+use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
 pub enum HeaderObject<'a> {
@@ -67,88 +86,221 @@ pub enum HeaderObject<'a> {
     ExtendedContentEncryption(ExtendedContentEncryptionData<'a>),
     DigitalSignature(DigitalSignatureData<'a>),
     Padding(usize),
-    Unknown(Object<'a>),
+    Unknown {
+        guid: Uuid,
+        data: Cow<'a, [u8]>,
+    },
+    Invalid {
+        guid: Uuid,
+        data: Cow<'a, [u8]>,
+        error: AsfError,
+    },
 }
 
 impl<'a> HeaderObject<'a> {
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &ParseOptions::default(), &Budget::default())
+    }
+
+    /// Parses an object, keeping it as `Invalid` if its body is malformed.
+    pub fn parse_lenient<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        Self::parse_with(input, &options, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
         let (input, obj) = object(input)?;
+        let Object { guid, data } = obj;
+        if !options.lenient {
+            let body = Self::parse_body(obj, options, budget).map_err(in_object(data, guid));
+            return Ok((input, body?));
+        }
         Ok((
             input,
-            match obj {
-                Object {
-                    guid: FILE_PROPERTIES_OBJECT,
-                    data,
-                } => (Self::FileProperties(FilePropertiesData::parse(data)?.1)),
-                Object {
-                    guid: STREAM_PROPERTIES_OBJECT,
-                    data,
-                } => (Self::StreamProperties(StreamPropertiesData::parse(data)?.1)),
-                Object {
-                    guid: HEADER_EXTENSION_OBJECT,
-                    data,
-                } => (Self::HeaderExtension(HeaderExtensionData::parse(data)?.1)),
-                Object {
-                    guid: CODEC_LIST_OBJECT,
-                    data,
-                } => (Self::CodecList(CodecListData::parse(data)?.1)),
-                Object {
-                    guid: SCRIPT_COMMAND_OBJECT,
-                    data,
-                } => (Self::ScriptCommand(ScriptCommandData::parse(data)?.1)),
-                Object {
-                    guid: MARKER_OBJECT,
-                    data,
-                } => (Self::Marker(MarkerData::parse(data)?.1)),
-                Object {
-                    guid: BITRATE_MUTUAL_EXCLUSION_OBJECT,
-                    data,
-                } => (Self::BitrateMutualExclusion(BitrateMutualExclusionData::parse(data)?.1)),
-                Object {
-                    guid: ERROR_CORRECTION_OBJECT,
-                    data,
-                } => (Self::ErrorCorrection(ErrorCorrectionData::parse(data)?.1)),
-                Object {
-                    guid: CONTENT_DESCRIPTION_OBJECT,
-                    data,
-                } => (Self::ContentDescription(ContentDescriptionData::parse(data)?.1)),
-                Object {
-                    guid: EXTENDED_CONTENT_DESCRIPTION_OBJECT,
-                    data,
-                } => {
-                    Self::ExtendedContentDescription(ExtendedContentDescriptionData::parse(data)?.1)
+            match Self::parse_body::<AsfError>(obj, options, budget).map_err(in_object(data, guid))
+            {
+                Ok(obj) => obj,
+                // Only exceeded limits fail outright, and those aren't the
+                // object's fault.
+                Err(Err::Failure(AsfError::LimitExceeded { .. })) => {
+                    return Err(in_object(data, guid)(limit_exceeded(data)))
                 }
-                Object {
-                    guid: STREAM_BITRATE_PROPERTIES_OBJECT,
-                    data,
-                } => (Self::StreamBitrateProperties(StreamBitratePropertiesData::parse(data)?.1)),
-                Object {
-                    guid: CONTENT_BRANDING_OBJECT,
-                    data,
-                } => (Self::ContentBranding(ContentBrandingData::parse(data)?.1)),
-                Object {
-                    guid: CONTENT_ENCRYPTION_OBJECT,
-                    data,
-                } => (Self::ContentEncryption(ContentEncryptionData::parse(data)?.1)),
-                Object {
-                    guid: EXTENDED_CONTENT_ENCRYPTION_OBJECT,
-                    data,
-                } => Self::ExtendedContentEncryption(ExtendedContentEncryptionData::parse(data)?.1),
-                Object {
-                    guid: DIGITAL_SIGNATURE_OBJECT,
-                    data,
-                } => (Self::DigitalSignature(DigitalSignatureData::parse(data)?.1)),
-                Object {
-                    guid: PADDING_OBJECT,
-                    data,
-                } => (Self::Padding(data.len())),
-                unknown => Self::Unknown(unknown),
+                Err(error) => Self::Invalid {
+                    guid,
+                    data: Cow::Borrowed(*data.fragment()),
+                    error: AsfError::from_err(error, data),
+                },
             },
         ))
     }
 
+    fn parse_body<E: ParseError<Span<'a>>>(
+        obj: Object<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> Result<Self, Err<E>> {
+        Ok(match obj {
+            Object {
+                guid: FILE_PROPERTIES_OBJECT,
+                data,
+            } => Self::FileProperties(FilePropertiesData::parse(data)?.1),
+            Object {
+                guid: STREAM_PROPERTIES_OBJECT,
+                data,
+            } => Self::StreamProperties(StreamPropertiesData::parse(data)?.1),
+            Object {
+                guid: HEADER_EXTENSION_OBJECT,
+                data,
+            } => Self::HeaderExtension(HeaderExtensionData::parse_with(data, options, budget)?.1),
+            Object {
+                guid: CODEC_LIST_OBJECT,
+                data,
+            } => Self::CodecList(CodecListData::parse(data)?.1),
+            Object {
+                guid: SCRIPT_COMMAND_OBJECT,
+                data,
+            } => Self::ScriptCommand(ScriptCommandData::parse_with(data, budget)?.1),
+            Object {
+                guid: MARKER_OBJECT,
+                data,
+            } => Self::Marker(MarkerData::parse_with(data, budget)?.1),
+            Object {
+                guid: BITRATE_MUTUAL_EXCLUSION_OBJECT,
+                data,
+            } => Self::BitrateMutualExclusion(BitrateMutualExclusionData::parse(data)?.1),
+            Object {
+                guid: ERROR_CORRECTION_OBJECT,
+                data,
+            } => Self::ErrorCorrection(ErrorCorrectionData::parse(data)?.1),
+            Object {
+                guid: CONTENT_DESCRIPTION_OBJECT,
+                data,
+            } => Self::ContentDescription(ContentDescriptionData::parse(data)?.1),
+            Object {
+                guid: EXTENDED_CONTENT_DESCRIPTION_OBJECT,
+                data,
+            } => Self::ExtendedContentDescription(
+                ExtendedContentDescriptionData::parse_with(data, budget)?.1,
+            ),
+            Object {
+                guid: STREAM_BITRATE_PROPERTIES_OBJECT,
+                data,
+            } => Self::StreamBitrateProperties(StreamBitratePropertiesData::parse(data)?.1),
+            Object {
+                guid: CONTENT_BRANDING_OBJECT,
+                data,
+            } => Self::ContentBranding(ContentBrandingData::parse(data)?.1),
+            Object {
+                guid: CONTENT_ENCRYPTION_OBJECT,
+                data,
+            } => Self::ContentEncryption(ContentEncryptionData::parse(data)?.1),
+            Object {
+                guid: EXTENDED_CONTENT_ENCRYPTION_OBJECT,
+                data,
+            } => Self::ExtendedContentEncryption(ExtendedContentEncryptionData::parse(data)?.1),
+            Object {
+                guid: DIGITAL_SIGNATURE_OBJECT,
+                data,
+            } => Self::DigitalSignature(DigitalSignatureData::parse(data)?.1),
+            Object {
+                guid: PADDING_OBJECT,
+                data,
+            } => Self::Padding(data.len()),
+            Object { guid, data } => Self::Unknown {
+                guid,
+                data: Cow::Borrowed(*data.fragment()),
+            },
+        })
+    }
+
     pub fn parse_many<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Vec<Self>, E> {
-        many0(complete(Self::parse))(input)
+        Self::parse_many_with(input, &ParseOptions::default(), &Budget::default())
+    }
+
+    pub(crate) fn parse_many_with<E: ParseError<Span<'a>>>(
+        mut input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Vec<Self>, E> {
+        let mut objects = Vec::new();
+        while !input.is_empty() {
+            let max = budget
+                .limits
+                .max_header_objects
+                .saturating_sub(objects.len());
+            budget.reserve::<Self, E>(input, 1, max)?;
+            let (rest, obj) =
+                match complete(|input| Self::parse_with(input, options, budget))(input) {
+                    Ok(result) => result,
+                    // Without a valid object size there is no way to find the
+                    // next object, so a lenient parse keeps the rest as one.
+                    Err(Err::Error(_)) if options.lenient => {
+                        let (guid, data, error) = unsized_object(input);
+                        let rest = input.slice(input.len()..);
+                        let obj = Self::Invalid {
+                            guid,
+                            data: Cow::Borrowed(*data.fragment()),
+                            error,
+                        };
+                        (rest, obj)
+                    }
+                    Err(error) => return Err(error),
+                };
+            objects.push(obj);
+            input = rest;
+        }
+        Ok((input, objects))
+    }
+
+    pub fn into_owned(self) -> HeaderObject<'static> {
+        match self {
+            HeaderObject::StreamProperties(data) => {
+                HeaderObject::StreamProperties(data.into_owned())
+            }
+            HeaderObject::HeaderExtension(data) => HeaderObject::HeaderExtension(data.into_owned()),
+            HeaderObject::CodecList(data) => HeaderObject::CodecList(data.into_owned()),
+            HeaderObject::ErrorCorrection(data) => HeaderObject::ErrorCorrection(data.into_owned()),
+            HeaderObject::ExtendedContentDescription(data) => {
+                HeaderObject::ExtendedContentDescription(data.into_owned())
+            }
+            HeaderObject::ContentBranding(data) => HeaderObject::ContentBranding(data.into_owned()),
+            HeaderObject::ContentEncryption(data) => {
+                HeaderObject::ContentEncryption(data.into_owned())
+            }
+            HeaderObject::ExtendedContentEncryption(data) => {
+                HeaderObject::ExtendedContentEncryption(data.into_owned())
+            }
+            HeaderObject::DigitalSignature(data) => {
+                HeaderObject::DigitalSignature(data.into_owned())
+            }
+            HeaderObject::FileProperties(data) => HeaderObject::FileProperties(data),
+            HeaderObject::ScriptCommand(data) => HeaderObject::ScriptCommand(data),
+            HeaderObject::Marker(data) => HeaderObject::Marker(data),
+            HeaderObject::BitrateMutualExclusion(data) => {
+                HeaderObject::BitrateMutualExclusion(data)
+            }
+            HeaderObject::ContentDescription(data) => HeaderObject::ContentDescription(data),
+            HeaderObject::StreamBitrateProperties(data) => {
+                HeaderObject::StreamBitrateProperties(data)
+            }
+            HeaderObject::Padding(data) => HeaderObject::Padding(data),
+            HeaderObject::Unknown { guid, data } => HeaderObject::Unknown {
+                guid,
+                data: Cow::Owned(data.into_owned()),
+            },
+            HeaderObject::Invalid { guid, data, error } => HeaderObject::Invalid {
+                guid,
+                data: Cow::Owned(data.into_owned()),
+                error,
+            },
+        }
     }
 
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
@@ -234,10 +386,15 @@ impl<'a> HeaderObject<'a> {
                 w.write_all(&data_len.to_le_bytes())?;
                 w.write_all(&vec![0u8; *size])?;
             }
-            HeaderObject::Unknown(unk) => {
-                w.write_all(&unk.guid.as_bytes_ms())?;
+            HeaderObject::Unknown { guid, data } => {
+                w.write_all(&guid.as_bytes_ms())?;
                 w.write_all(&data_len.to_le_bytes())?;
-                w.write_all(&unk.data)?;
+                w.write_all(data)?;
+            }
+            HeaderObject::Invalid { guid, data, .. } => {
+                w.write_all(&guid.as_bytes_ms())?;
+                w.write_all(&data_len.to_le_bytes())?;
+                w.write_all(data)?;
             }
         }
         Ok(())
@@ -264,7 +421,8 @@ impl<'a> HeaderObject<'a> {
             HeaderObject::ExtendedContentEncryption(data) => data.size_of(),
             HeaderObject::DigitalSignature(data) => data.size_of(),
             HeaderObject::Padding(size) => *size,
-            HeaderObject::Unknown(unk) => unk.data.len(),
+            HeaderObject::Unknown { data, .. } => data.len(),
+            HeaderObject::Invalid { data, .. } => data.len(),
         };
         len
     }
@@ -278,21 +436,245 @@ pub struct HeaderObjects<'a> {
 }
 
 impl<'a> HeaderObjects<'a> {
+    pub fn file_properties(&self) -> Option<&FilePropertiesData> {
+        self.objects.iter().find_map(|object| match object {
+            HeaderObject::FileProperties(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    pub fn file_properties_mut(&mut self) -> Option<&mut FilePropertiesData> {
+        self.objects.iter_mut().find_map(|object| match object {
+            HeaderObject::FileProperties(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    pub fn stream_properties(&self) -> impl Iterator<Item = &StreamPropertiesData<'a>> {
+        self.objects.iter().filter_map(|object| match object {
+            HeaderObject::StreamProperties(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// The objects inside the header extension object.
+    pub fn extension_objects(&self) -> impl Iterator<Item = &ExtensionHeaderObject<'a>> {
+        self.objects
+            .iter()
+            .filter_map(|object| match object {
+                HeaderObject::HeaderExtension(data) => Some(data.extension_objects.iter()),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    pub fn extension_objects_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut ExtensionHeaderObject<'a>> {
+        self.objects
+            .iter_mut()
+            .filter_map(|object| match object {
+                HeaderObject::HeaderExtension(data) => Some(data.extension_objects.iter_mut()),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    pub fn language_list(&self) -> Option<&LanguageListData> {
+        self.extension_objects().find_map(|object| match object {
+            ExtensionHeaderObject::LanguageList(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// The header extension object, adding an empty one if there is none.
+    pub fn header_extension_mut(&mut self) -> &mut HeaderExtensionData<'a> {
+        let found = self
+            .objects
+            .iter()
+            .position(|object| matches!(object, HeaderObject::HeaderExtension(_)));
+        let index = found.unwrap_or_else(|| {
+            self.objects
+                .push(HeaderObject::HeaderExtension(HeaderExtensionData {
+                    reserved_1: RESERVED_1,
+                    reserved_2: 6,
+                    extension_objects: Vec::new(),
+                }));
+            self.objects.len() - 1
+        });
+        match &mut self.objects[index] {
+            HeaderObject::HeaderExtension(data) => data,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The language list, adding an empty one, and a header extension object
+    /// to hold it, if there is none.
+    pub fn language_list_mut(&mut self) -> &mut LanguageListData {
+        if self.language_list().is_none() {
+            let list = ExtensionHeaderObject::LanguageList(LanguageListData {
+                language_id_records: Vec::new(),
+            });
+            self.header_extension_mut().extension_objects.push(list);
+        }
+        self.extension_objects_mut()
+            .find_map(|object| match object {
+                ExtensionHeaderObject::LanguageList(data) => Some(data),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Resolves a language list index, as found in extended stream
+    /// properties, stream names and metadata library records, to a language
+    /// tag such as `en-us`.
+    pub fn language(&self, index: u16) -> Option<String> {
+        self.language_list()?.language(index)
+    }
+
+    /// The language list index for `tag`, ignoring ASCII case. See
+    /// `LanguageListData::index_of`.
+    pub fn language_index(&self, tag: &str) -> Option<u16> {
+        self.language_list()?.index_of(tag)
+    }
+
+    /// The language list index for `tag`, adding it to the list, and the list
+    /// to the header, if needed. See `LanguageListData::add`.
+    pub fn add_language(&mut self, tag: &str) -> Option<u16> {
+        self.language_list_mut().add(tag)
+    }
+
+    /// Sets the language of a stream with extended stream properties. Returns
+    /// `None` if there are none for `stream_number` or the tag cannot be
+    /// added.
+    pub fn set_stream_language(&mut self, stream_number: u16, tag: &str) -> Option<()> {
+        self.extended_stream_properties_mut(stream_number)?;
+        let index = self.add_language(tag)?;
+        self.extended_stream_properties_mut(stream_number)?
+            .stream_language_id_index = index;
+        Some(())
+    }
+
+    /// Sets the language of the `name`th stream name in the extended stream
+    /// properties of a stream. Returns `None` if there is no such name or the
+    /// tag cannot be added.
+    pub fn set_stream_name_language(
+        &mut self,
+        stream_number: u16,
+        name: usize,
+        tag: &str,
+    ) -> Option<()> {
+        self.extended_stream_properties_mut(stream_number)?
+            .stream_names
+            .get(name)?;
+        let index = self.add_language(tag)?;
+        self.extended_stream_properties_mut(stream_number)?
+            .stream_names[name]
+            .language_id_index = index;
+        Some(())
+    }
+
+    /// Sets the language of the `record`th description record in the
+    /// metadata library. Returns `None` if there is no such record or the tag
+    /// cannot be added.
+    pub fn set_metadata_library_language(&mut self, record: usize, tag: &str) -> Option<()> {
+        self.metadata_library_mut()?
+            .description_records
+            .get(record)?;
+        let index = self.add_language(tag)?;
+        self.metadata_library_mut()?.description_records[record].language_list_index = index;
+        Some(())
+    }
+
+    fn extended_stream_properties_mut(
+        &mut self,
+        stream_number: u16,
+    ) -> Option<&mut ExtendedStreamPropertiesData<'a>> {
+        self.extension_objects_mut()
+            .find_map(|object| match object {
+                ExtensionHeaderObject::ExtendedStreamProperties(data)
+                    if data.stream_number == stream_number =>
+                {
+                    Some(data)
+                }
+                _ => None,
+            })
+    }
+
+    fn metadata_library_mut(&mut self) -> Option<&mut MetadataLibraryData<'a>> {
+        self.extension_objects_mut()
+            .find_map(|object| match object {
+                ExtensionHeaderObject::MetadataLibrary(data) => Some(data),
+                _ => None,
+            })
+    }
+
+    /// Joins the header objects describing each stream. See `Stream`.
+    pub fn streams(&self) -> Vec<Stream<'_, 'a>> {
+        crate::stream::streams(self)
+    }
+
+    /// Picks the streams to use under `constraints`. See `select`.
+    pub fn select_streams(&self, constraints: &Constraints) -> Selection {
+        crate::select::select_streams(self, constraints)
+    }
+
+    /// Classifies the file by its streams. See `MediaType::classify`.
+    pub fn media_type(&self) -> MediaType {
+        MediaType::classify(self)
+    }
+
     pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
-        let (input, _guid) = tag(HEADER_OBJECT.as_bytes_ms())(input)?;
-        let (input, size) = le_u64(input)?;
-        let (input, _num_header_objs) = le_u32(input)?;
-        let (input, reserved1) = le_u8(input)?;
-        let (input, reserved2) = le_u8(input)?;
-        let (input, data) = take(size - 30)(input)?;
-        Ok((
-            input,
-            Self {
-                reserved1,
-                reserved2,
-                objects: HeaderObject::parse_many(data)?.1,
-            },
-        ))
+        Self::parser(&ParseOptions::default())(input)
+    }
+
+    /// Parses the header object. With `ParseOptions::lenient`, objects that
+    /// fail to parse are kept as `HeaderObject::Invalid`.
+    pub fn parser<E: ParseError<Span<'a>>>(
+        options: &ParseOptions,
+    ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Self, E> {
+        let options = *options;
+        move |input: Span<'a>| Self::parse_with(input, &options, &Budget::new(options.limits))
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
+        context("header", |input: Span<'a>| {
+            let (input, _guid) = guid_tag(HEADER_OBJECT)(input)?;
+            let size_input = input;
+            let (input, size) = le_u64(input)?;
+            let (input, _num_header_objs) = le_u32(input)?;
+            let (input, reserved1) = le_u8(input)?;
+            let (input, reserved2) = le_u8(input)?;
+            let data_len = size
+                .checked_sub(30)
+                .ok_or_else(|| Err::Error(E::from_size_mismatch(size_input, 30, size)))?;
+            let (input, data) = take(data_len)(input)?;
+            let (_, objects) = HeaderObject::parse_many_with(data, options, budget)?;
+            Ok((
+                input,
+                Self {
+                    reserved1,
+                    reserved2,
+                    objects,
+                },
+            ))
+        })(input)
+    }
+
+    pub fn into_owned(self) -> HeaderObjects<'static> {
+        HeaderObjects {
+            reserved1: self.reserved1,
+            reserved2: self.reserved2,
+            objects: self
+                .objects
+                .into_iter()
+                .map(HeaderObject::into_owned)
+                .collect(),
+        }
     }
 
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
@@ -322,3 +704,91 @@ impl<'a> HeaderObjects<'a> {
         len
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::find_guid, Container};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../../samples/basic.wmv");
+
+    #[test]
+    fn lenient_header_wmv() {
+        // Give the first codec entry a name far longer than the object.
+        let mut buf = BASIC_WMV.to_vec();
+        let codec_list = find_guid(&buf, CODEC_LIST_OBJECT);
+        let name_len = codec_list + 24 + 16 + 4 + 2;
+        buf[name_len..name_len + 2].copy_from_slice(&0xffffu16.to_le_bytes());
+
+        assert!(Container::parse::<AsfError>(Span::new(&buf)).is_err());
+
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (remaining, container) =
+            Container::parse_with_options::<AsfError>(Span::new(&buf), &options)
+                .expect("to parse successfully");
+        assert_eq!(remaining.len(), 0);
+        let original = Container::parse::<AsfError>(Span::new(BASIC_WMV))
+            .unwrap()
+            .1;
+        assert_eq!(
+            container.header.objects.len(),
+            original.header.objects.len()
+        );
+        assert!(container.header.objects.iter().any(|object| matches!(
+            object,
+            HeaderObject::Invalid {
+                guid: CODEC_LIST_OBJECT,
+                ..
+            }
+        )));
+        assert_eq!(container.data, original.data);
+
+        let mut out = Vec::new();
+        container.write(&mut out).expect("to write successfully");
+        assert_eq!(&out[..codec_list], &buf[..codec_list]);
+    }
+
+    #[test]
+    fn lenient_unsized_header_object() {
+        // Give the codec list a size past the end of the header.
+        let mut buf = BASIC_WMV.to_vec();
+        let codec_list = find_guid(&buf, CODEC_LIST_OBJECT);
+        buf[codec_list + 16..codec_list + 24].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (remaining, container) =
+            Container::parse_with_options::<AsfError>(Span::new(&buf), &options)
+                .expect("to parse successfully");
+        assert_eq!(remaining.len(), 0);
+        let original = Container::parse::<AsfError>(Span::new(BASIC_WMV))
+            .unwrap()
+            .1;
+        let index = original
+            .header
+            .objects
+            .iter()
+            .position(|object| matches!(object, HeaderObject::CodecList(_)))
+            .unwrap();
+        assert_eq!(container.header.objects.len(), index + 1);
+        assert_eq!(
+            container.header.objects[..index],
+            original.header.objects[..index]
+        );
+        let data_object = find_guid(&buf, DATA_OBJECT);
+        match &container.header.objects[index] {
+            HeaderObject::Invalid { guid, data, error } => {
+                assert_eq!(*guid, CODEC_LIST_OBJECT);
+                assert_eq!(data[..], buf[codec_list + 24..data_object]);
+                assert_eq!(error.offset(), data_object);
+            }
+            object => panic!("expected an invalid object, got {:?}", object),
+        }
+        assert_eq!(container.data, original.data);
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{guid::*, span::Span, widestr::*};
+use crate::{
+    combinators::count, error::ParseError, guid::*, options::Budget, span::Span, widestr::*,
+};
 use nom::{
-    error::ParseError,
-    multi::count,
     number::streaming::{le_u16, le_u32},
     IResult,
 };
@@ -55,11 +55,21 @@ impl Command {
 
 impl ScriptCommandData {
     pub fn parse<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &Budget::default())
+    }
+
+    pub(crate) fn parse_with<'a, E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
         let (input, reserved) = guid(input)?;
         let (input, commands_count) = le_u16(input)?;
         let (input, command_types_count) = le_u16(input)?;
+        let max = budget.limits.max_script_commands;
+        budget.reserve::<WideStr, E>(input, command_types_count.into(), max)?;
         let (input, command_types) =
             count(WideStr::parse_count16, command_types_count.into())(input)?;
+        budget.reserve::<Command, E>(input, commands_count.into(), max)?;
         let (input, commands) = count(Command::parse, commands_count.into())(input)?;
         Ok((
             input,
@@ -91,7 +101,6 @@ impl ScriptCommandData {
         len += 16;
         len += 2;
         len += 2;
-        len += 2;
         for command_type in self.command_types.iter() {
             len += command_type.size_of_count16();
         }
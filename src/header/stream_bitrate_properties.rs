@@ -1,6 +1,5 @@
-use crate::span::Span;
+use crate::{error::ParseError, span::Span};
 use nom::{
-    error::ParseError,
     multi::length_count,
     number::streaming::{le_u16, le_u32},
     IResult,
@@ -1,5 +1,5 @@
-use crate::span::Span;
-use nom::{error::ParseError, multi::length_count, number::streaming::le_u16, IResult};
+use crate::{error::ParseError, span::Span};
+use nom::{multi::length_count, number::streaming::le_u16, IResult};
 use std::{convert::TryInto, io::Write};
 
 #[derive(Debug, PartialEq)]
@@ -1,11 +1,10 @@
-use crate::{guid::*, span::Span};
+use crate::{combinators::span_bytes, error::ParseError, guid::*, span::Span};
 use nom::{
     bytes::streaming::take,
-    error::ParseError,
     number::streaming::{le_u16, le_u32, le_u64},
     IResult,
 };
-use std::{convert::TryInto, io::Write};
+use std::{borrow::Cow, convert::TryInto, io::Write};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
@@ -15,8 +14,8 @@ pub struct StreamPropertiesData<'a> {
     pub time_offset: u64,
     pub flags: u16,
     pub reserved: u32,
-    pub type_specific_data: Span<'a>,
-    pub error_correction_data: Span<'a>,
+    pub type_specific_data: Cow<'a, [u8]>,
+    pub error_correction_data: Cow<'a, [u8]>,
 }
 
 impl<'a> StreamPropertiesData<'a> {
@@ -28,8 +27,8 @@ impl<'a> StreamPropertiesData<'a> {
         let (input, error_correction_data_len) = le_u32(input)?;
         let (input, flags) = le_u16(input)?;
         let (input, reserved) = le_u32(input)?;
-        let (input, type_specific_data) = take(type_specific_data_len)(input)?;
-        let (input, error_correction_data) = take(error_correction_data_len)(input)?;
+        let (input, type_specific_data) = span_bytes(take(type_specific_data_len))(input)?;
+        let (input, error_correction_data) = span_bytes(take(error_correction_data_len))(input)?;
 
         Ok((
             input,
@@ -45,6 +44,22 @@ impl<'a> StreamPropertiesData<'a> {
         ))
     }
 
+    pub fn stream_number(&self) -> u16 {
+        self.flags & 0x7f
+    }
+
+    pub fn into_owned(self) -> StreamPropertiesData<'static> {
+        StreamPropertiesData {
+            stream_type: self.stream_type,
+            error_correction_type: self.error_correction_type,
+            time_offset: self.time_offset,
+            flags: self.flags,
+            reserved: self.reserved,
+            type_specific_data: Cow::Owned(self.type_specific_data.into_owned()),
+            error_correction_data: Cow::Owned(self.error_correction_data.into_owned()),
+        }
+    }
+
     pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
         let type_specific_data_len: u32 = self.type_specific_data.len().try_into()?;
         let error_correction_data_len: u32 = self.error_correction_data.len().try_into()?;
@@ -77,13 +92,13 @@ impl<'a> StreamPropertiesData<'a> {
 
 #[cfg(test)]
 mod tests {
-    use nom::{error::VerboseError, AsBytes, Slice};
+    use nom::{error::VerboseError, AsBytes};
 
     use crate::header::*;
 
     use super::*;
 
-    const BASIC_STREAM_PROPERTIES_BYTES: &'static [u8] = &[
+    const BASIC_STREAM_PROPERTIES_BYTES: &[u8] = &[
         0x91, 0x07, 0xDC, 0xB7, 0xB7, 0xA9, 0xCF, 0x11, 0x8E, 0xE6, 0x00, 0xC0, 0x0C, 0x20, 0x53,
         0x65, 0x72, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x9E, 0x69, 0xF8, 0x4D, 0x5B,
         0xCF, 0x11, 0xA8, 0xFD, 0x00, 0x80, 0x5F, 0x5C, 0x44, 0x2B, 0x50, 0xCD, 0xC3, 0xBF, 0x8F,
@@ -95,15 +110,14 @@ mod tests {
     ];
 
     fn basic_stream_properties_data() -> StreamPropertiesData<'static> {
-        let span = Span::new(BASIC_STREAM_PROPERTIES_BYTES);
         StreamPropertiesData {
             stream_type: AUDIO_MEDIA,
             error_correction_type: AUDIO_SPREAD,
             time_offset: 0,
             flags: 1,
             reserved: 7811952,
-            type_specific_data: span.slice(78..106),
-            error_correction_data: span.slice(106..),
+            type_specific_data: Cow::Borrowed(&BASIC_STREAM_PROPERTIES_BYTES[78..106]),
+            error_correction_data: Cow::Borrowed(&BASIC_STREAM_PROPERTIES_BYTES[106..]),
         }
     }
 
@@ -125,7 +139,7 @@ mod tests {
             .write(&mut buf)
             .expect("write to succeed");
 
-        assert_eq!(buf.as_bytes(), &BASIC_STREAM_PROPERTIES_BYTES[..])
+        assert_eq!(buf.as_bytes(), BASIC_STREAM_PROPERTIES_BYTES)
     }
 
     #[test]
@@ -1,25 +1,221 @@
-use crate::{object::*, span::Span};
-use nom::{bytes::streaming::take, combinator::complete, error::ParseError, multi::many0, IResult};
+pub mod simple_index;
 
-#[derive(Debug, PartialEq)]
-pub struct IndexObject {}
+use self::simple_index::SimpleIndexData;
+use crate::{
+    error::{in_object, AsfError, ParseError},
+    guid::*,
+    object::*,
+    options::{limit_exceeded, Budget, ParseOptions},
+    span::Span,
+};
+use nom::{combinator::complete, Err, IResult, Slice};
+use std::{borrow::Cow, convert::TryInto, io::Write};
+use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
-pub struct IndexObjects {
-    pub objects: Vec<IndexObject>,
+pub enum IndexObject<'a> {
+    SimpleIndex(SimpleIndexData),
+    Unknown {
+        guid: Uuid,
+        data: Cow<'a, [u8]>,
+    },
+    Invalid {
+        guid: Uuid,
+        data: Cow<'a, [u8]>,
+        error: AsfError,
+    },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexObjects<'a> {
+    pub objects: Vec<IndexObject<'a>>,
 }
 
-impl IndexObject {
-    pub fn parse<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
-        let (input, header) = object_header(input)?;
-        let (input, _data) = take(header.size - 24)(input)?;
-        Ok((input, IndexObject {}))
+impl<'a> IndexObject<'a> {
+    pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &ParseOptions::default())
+    }
+
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        options: &ParseOptions,
+    ) -> IResult<Span<'a>, Self, E> {
+        let (input, obj) = object(input)?;
+        let Object { guid, data } = obj;
+        if !options.lenient {
+            let body = Self::parse_body(obj).map_err(in_object(data, guid));
+            return Ok((input, body?));
+        }
+        Ok((
+            input,
+            match Self::parse_body::<AsfError>(obj).map_err(in_object(data, guid)) {
+                Ok(obj) => obj,
+                Err(Err::Failure(AsfError::LimitExceeded { .. })) => {
+                    return Err(in_object(data, guid)(limit_exceeded(data)))
+                }
+                Err(error) => Self::Invalid {
+                    guid,
+                    data: Cow::Borrowed(*data.fragment()),
+                    error: AsfError::from_err(error, data),
+                },
+            },
+        ))
+    }
+
+    fn parse_body<E: ParseError<Span<'a>>>(obj: Object<'a>) -> Result<Self, Err<E>> {
+        Ok(match obj {
+            Object {
+                guid: SIMPLE_INDEX_OBJECT,
+                data,
+            } => Self::SimpleIndex(SimpleIndexData::parse(data)?.1),
+            Object { guid, data } => Self::Unknown {
+                guid,
+                data: Cow::Borrowed(*data.fragment()),
+            },
+        })
+    }
+
+    pub fn into_owned(self) -> IndexObject<'static> {
+        match self {
+            IndexObject::SimpleIndex(data) => IndexObject::SimpleIndex(data),
+            IndexObject::Unknown { guid, data } => IndexObject::Unknown {
+                guid,
+                data: Cow::Owned(data.into_owned()),
+            },
+            IndexObject::Invalid { guid, data, error } => IndexObject::Invalid {
+                guid,
+                data: Cow::Owned(data.into_owned()),
+                error,
+            },
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let data_len: u64 = self.size_of().try_into()?;
+        match self {
+            IndexObject::SimpleIndex(data) => {
+                w.write_all(&SIMPLE_INDEX_OBJECT.as_bytes_ms())?;
+                w.write_all(&data_len.to_le_bytes())?;
+                data.write(w)?;
+            }
+            IndexObject::Unknown { guid, data } | IndexObject::Invalid { guid, data, .. } => {
+                w.write_all(&guid.as_bytes_ms())?;
+                w.write_all(&data_len.to_le_bytes())?;
+                w.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += 16;
+        len += 8;
+        len += match self {
+            IndexObject::SimpleIndex(data) => data.size_of(),
+            IndexObject::Unknown { data, .. } => data.len(),
+            IndexObject::Invalid { data, .. } => data.len(),
+        };
+        len
     }
 }
 
-impl IndexObjects {
-    pub fn parse<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
-        let (input, objects) = many0(complete(IndexObject::parse))(input)?;
+impl<'a> IndexObjects<'a> {
+    pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        Self::parse_with(input, &ParseOptions::default(), &Budget::default())
+    }
+
+    /// Parses the objects after the data object up to the end of the input.
+    /// A lenient parse keeps objects that fail to parse as
+    /// `IndexObject::Invalid`.
+    pub(crate) fn parse_with<E: ParseError<Span<'a>>>(
+        mut input: Span<'a>,
+        options: &ParseOptions,
+        budget: &Budget,
+    ) -> IResult<Span<'a>, Self, E> {
+        let mut objects = Vec::new();
+        while !input.is_empty() {
+            budget.reserve::<IndexObject, E>(input, 1, usize::MAX)?;
+            let (rest, obj) = match complete(|input| IndexObject::parse_with(input, options))(input)
+            {
+                Ok(result) => result,
+                // Without a valid object size there is no way to find the
+                // next object, so a lenient parse keeps the rest as one.
+                Err(Err::Error(_)) if options.lenient => {
+                    let (guid, data, error) = unsized_object(input);
+                    let rest = input.slice(input.len()..);
+                    let obj = IndexObject::Invalid {
+                        guid,
+                        data: Cow::Borrowed(*data.fragment()),
+                        error,
+                    };
+                    (rest, obj)
+                }
+                Err(error) => return Err(error),
+            };
+            objects.push(obj);
+            input = rest;
+        }
         Ok((input, Self { objects }))
     }
+
+    pub fn into_owned(self) -> IndexObjects<'static> {
+        IndexObjects {
+            objects: self
+                .objects
+                .into_iter()
+                .map(IndexObject::into_owned)
+                .collect(),
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        for object in self.objects.iter() {
+            object.write(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        for object in self.objects.iter() {
+            len += object.size_of();
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::find_guid;
+
+    const BASIC_WMV: &[u8] = include_bytes!("../../samples/basic.wmv");
+
+    #[test]
+    fn damaged_simple_index() {
+        // Claim far more index entries than the object holds.
+        let mut buf = BASIC_WMV.to_vec();
+        let index = find_guid(&buf, SIMPLE_INDEX_OBJECT);
+        let entries_count = index + 24 + 16 + 8 + 4;
+        buf[entries_count..entries_count + 4].fill(0xff);
+
+        crate::parse(&buf).expect_err("expected a bad index to fail");
+
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let container = crate::parse_with_options(&buf, &options).expect("to parse successfully");
+        match &container.indices.objects[..] {
+            [IndexObject::Invalid { guid, data, .. }] => {
+                assert_eq!(*guid, SIMPLE_INDEX_OBJECT);
+                assert_eq!(data[..], buf[index + 24..]);
+            }
+            objects => panic!("expected an invalid index, got {:?}", objects),
+        }
+        let mut out = Vec::new();
+        container.write(&mut out).expect("to write successfully");
+        assert_eq!(out[index..], buf[index..]);
+    }
 }
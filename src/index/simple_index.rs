@@ -0,0 +1,92 @@
+use crate::{combinators::count, error::ParseError, guid::*, span::Span};
+use nom::{
+    number::streaming::{le_u16, le_u32, le_u64},
+    IResult,
+};
+use std::{convert::TryInto, io::Write};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq)]
+pub struct SimpleIndexEntry {
+    pub packet_number: u32,
+    pub packet_count: u16,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SimpleIndexData {
+    pub file_id: Uuid,
+    pub index_entry_time_interval: u64,
+    pub maximum_packet_count: u32,
+    pub index_entries: Vec<SimpleIndexEntry>,
+}
+
+impl SimpleIndexEntry {
+    pub fn parse<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        let (input, packet_number) = le_u32(input)?;
+        let (input, packet_count) = le_u16(input)?;
+        Ok((
+            input,
+            Self {
+                packet_number,
+                packet_count,
+            },
+        ))
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        w.write_all(&self.packet_number.to_le_bytes())?;
+        w.write_all(&self.packet_count.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += 4;
+        len += 2;
+        len
+    }
+}
+
+impl SimpleIndexData {
+    pub fn parse<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Self, E> {
+        let (input, file_id) = guid(input)?;
+        let (input, index_entry_time_interval) = le_u64(input)?;
+        let (input, maximum_packet_count) = le_u32(input)?;
+        let (input, index_entries_count) = le_u32(input)?;
+        let (input, index_entries) =
+            count(SimpleIndexEntry::parse, index_entries_count as _)(input)?;
+        Ok((
+            input,
+            Self {
+                file_id,
+                index_entry_time_interval,
+                maximum_packet_count,
+                index_entries,
+            },
+        ))
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let index_entries_len: u32 = self.index_entries.len().try_into()?;
+        w.write_all(&self.file_id.as_bytes_ms())?;
+        w.write_all(&self.index_entry_time_interval.to_le_bytes())?;
+        w.write_all(&self.maximum_packet_count.to_le_bytes())?;
+        w.write_all(&index_entries_len.to_le_bytes())?;
+        for index_entry in self.index_entries.iter() {
+            index_entry.write(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += 16;
+        len += 8;
+        len += 4;
+        len += 4;
+        for index_entry in self.index_entries.iter() {
+            len += index_entry.size_of();
+        }
+        len
+    }
+}
@@ -2,33 +2,81 @@ extern crate nom;
 extern crate nom_locate;
 extern crate uuid;
 
+#[cfg(feature = "tokio")]
+pub mod async_io;
 pub(crate) mod combinators;
 pub mod data;
 pub mod error;
+pub mod finalize;
 pub mod guid;
 pub mod header;
 pub mod index;
+pub mod merge;
+#[cfg(feature = "mmap")]
+pub mod mmap;
 pub mod object;
+pub mod options;
+pub mod probe;
+pub mod push;
+pub mod remux;
+pub mod select;
+#[cfg(feature = "bytes")]
+pub mod shared;
+pub mod sniff;
 pub mod span;
+pub mod stream;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod trim;
 pub mod widestr;
 
 use crate::{
-    data::DataObject, error::Error, header::HeaderObjects, index::IndexObjects, span::Span,
+    data::DataObject,
+    error::{AsfError, ParseError},
+    header::HeaderObjects,
+    index::IndexObjects,
+    options::{Budget, ParseOptions},
+    select::{Constraints, Selection},
+    sniff::MediaType,
+    span::Span,
+    stream::Stream,
 };
-use nom::{Err, IResult};
+use nom::IResult;
+use std::io::Write;
+
+#[cfg(feature = "mmap")]
+pub use mmap::open_mmap;
+pub use probe::probe;
+pub use sniff::sniff;
 
 #[derive(Debug, PartialEq)]
 pub struct Container<'a> {
     pub header: HeaderObjects<'a>,
     pub data: DataObject<'a>,
-    pub indices: IndexObjects,
+    pub indices: IndexObjects<'a>,
 }
 
 impl<'a> Container<'a> {
-    pub(crate) fn parse(input: Span<'a>) -> IResult<Span<'a>, Container, Error<Span<'a>>> {
-        let (input, header) = HeaderObjects::parse(input)?;
-        let (input, data) = DataObject::parse(input)?;
-        let (input, indices) = IndexObjects::parse(input)?;
+    /// Parses a whole file. Unlike the top-level `parse`, this lets the
+    /// caller pick the error type, such as `nom::error::Error` when only
+    /// success matters.
+    pub fn parse<E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Container<'a>, E> {
+        Self::parse_with_options(input, &ParseOptions::default())
+    }
+
+    pub fn parse_with_options<E: ParseError<Span<'a>>>(
+        input: Span<'a>,
+        options: &ParseOptions,
+    ) -> IResult<Span<'a>, Container<'a>, E> {
+        let budget = Budget::new(options.limits);
+        let (input, header) = HeaderObjects::parse_with(input, options, &budget)?;
+        let (input, data) =
+            DataObject::parse_with(input, header.file_properties(), options, &budget)?;
+        // A truncated file has nothing left after the packets we kept.
+        let (input, indices) = match data.truncation {
+            Some(_) => (input, IndexObjects::default()),
+            None => IndexObjects::parse_with(input, options, &budget)?,
+        };
         Ok((
             input,
             Container {
@@ -38,22 +86,159 @@ impl<'a> Container<'a> {
             },
         ))
     }
+
+    pub fn media_type(&self) -> MediaType {
+        self.header.media_type()
+    }
+
+    pub fn streams(&self) -> Vec<Stream<'_, 'a>> {
+        self.header.streams()
+    }
+
+    pub fn select_streams(&self, constraints: &Constraints) -> Selection {
+        self.header.select_streams(constraints)
+    }
+
+    /// Copies everything still borrowed from the input, so the container
+    /// can outlive the file buffer or move to another thread.
+    pub fn into_owned(self) -> Container<'static> {
+        Container {
+            header: self.header.into_owned(),
+            data: self.data.into_owned(),
+            indices: self.indices.into_owned(),
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        self.header.write(w)?;
+        self.data.write(w)?;
+        self.indices.write(w)?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += self.header.size_of();
+        len += self.data.size_of();
+        len += self.indices.size_of();
+        len
+    }
+}
+
+pub fn parse<'a>(data: &'a [u8]) -> Result<Container<'a>, AsfError> {
+    let input = Span::new(data);
+    Container::parse(input)
+        .map(|(_, container)| container)
+        .map_err(|error| AsfError::from_err(error, input))
 }
 
-pub fn parse<'a>(data: &'a [u8]) -> Result<Container, Err<Error<Span<'a>>>> {
-    Ok(Container::parse(Span::new(data))?.1)
+pub fn parse_with_options<'a>(
+    data: &'a [u8],
+    options: &ParseOptions,
+) -> Result<Container<'a>, AsfError> {
+    let input = Span::new(data);
+    Container::parse_with_options(input, options)
+        .map(|(_, container)| container)
+        .map_err(|error| AsfError::from_err(error, input))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::find_guid;
 
-    const BASIC_WMV: &'static [u8] = include_bytes!("../samples/basic.wmv");
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+    const BASIC_DRMV2_WMA: &[u8] = include_bytes!("../samples/basic-drmv2.wma");
 
     #[test]
     fn basic_wmv() {
         let (remaining, _data) =
-            Container::parse(Span::new(BASIC_WMV)).expect("to parse successfully");
+            Container::parse::<AsfError>(Span::new(BASIC_WMV)).expect("to parse successfully");
         assert_eq!(remaining.len(), 0);
     }
+
+    #[test]
+    fn error_types() {
+        let (_, container) =
+            Container::parse::<AsfError>(Span::new(BASIC_WMV)).expect("to parse successfully");
+        let (_, simple) = Container::parse::<nom::error::Error<_>>(Span::new(BASIC_WMV))
+            .expect("to parse successfully");
+        let (_, verbose) = Container::parse::<nom::error::VerboseError<_>>(Span::new(BASIC_WMV))
+            .expect("to parse successfully");
+        assert_eq!(simple, container);
+        assert_eq!(verbose, container);
+
+        // Damaged packets are still described in full.
+        let packets = find_guid(BASIC_WMV, guid::DATA_OBJECT) + 50;
+        let mut buf = BASIC_WMV.to_vec();
+        buf[packets..packets + 16].fill(0xff);
+        let options = ParseOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let (_, simple) =
+            Container::parse_with_options::<nom::error::Error<_>>(Span::new(&buf), &options)
+                .expect("to parse successfully");
+        let (_, container) = Container::parse_with_options::<AsfError>(Span::new(&buf), &options)
+            .expect("to parse successfully");
+        assert_eq!(simple.data.damaged, container.data.damaged);
+    }
+
+    #[test]
+    fn into_owned() {
+        let buf = BASIC_WMV.to_vec();
+        let owned = parse(&buf).expect("to parse successfully").into_owned();
+        drop(buf);
+
+        let owned = std::thread::spawn(move || owned).join().unwrap();
+        assert_eq!(owned, parse(BASIC_WMV).unwrap());
+        let mut out = Vec::new();
+        owned.write(&mut out).expect("write to succeed");
+        assert_eq!(out.len(), BASIC_WMV.len());
+    }
+
+    #[test]
+    fn write_basic_wmv() {
+        let (_, container) =
+            Container::parse::<AsfError>(Span::new(BASIC_WMV)).expect("to parse successfully");
+        let mut buf = Vec::new();
+        container.write(&mut buf).expect("write to succeed");
+        assert_eq!(container.size_of(), BASIC_WMV.len());
+        assert_eq!(buf.len(), BASIC_WMV.len());
+
+        // Packet padding is written as zeroes, so compare the parsed results.
+        let (_, rewritten) =
+            Container::parse::<AsfError>(Span::new(&buf)).expect("to parse successfully");
+        assert_eq!(rewritten, container);
+    }
+
+    #[test]
+    fn write_basic_drmv2_wma() {
+        let (_, container) = Container::parse::<AsfError>(Span::new(BASIC_DRMV2_WMA))
+            .expect("to parse successfully");
+        let mut buf = Vec::new();
+        container.write(&mut buf).expect("write to succeed");
+        assert_eq!(container.size_of(), BASIC_DRMV2_WMA.len());
+        assert_eq!(buf, BASIC_DRMV2_WMA);
+    }
+
+    #[test]
+    fn malformed_sizes() {
+        let header = find_guid(BASIC_WMV, guid::HEADER_OBJECT);
+        let props = find_guid(BASIC_WMV, guid::FILE_PROPERTIES_OBJECT);
+        let data = find_guid(BASIC_WMV, guid::DATA_OBJECT);
+        let patches: [(usize, &[u8]); 4] = [
+            // Object sizes smaller than the object headers themselves.
+            (header + 16, &[0; 8]),
+            (props + 16, &[0; 8]),
+            (data + 16, &[0; 8]),
+            // Far more packets than could possibly fit.
+            (data + 40, &[0xff; 8]),
+        ];
+        for &(offset, patch) in patches.iter() {
+            let mut buf = BASIC_WMV.to_vec();
+            buf[offset..offset + patch.len()].copy_from_slice(patch);
+            assert!(Container::parse::<AsfError>(Span::new(&buf)).is_err());
+        }
+    }
 }
@@ -0,0 +1,451 @@
+//! Putting the streams of several files into one file, the reverse of
+//! dropping streams with `remux`.
+
+use crate::{
+    data::{
+        packetize::{packetize, unpack},
+        packets::Payload,
+    },
+    finalize::refresh,
+    guid::*,
+    header::{
+        advanced_mutual_exclusion::AdvancedMutualExclusionData, file_properties::BROADCAST_FLAG,
+        group_mutual_exclusion::GroupMutualExclusionData, header_extension::ExtensionHeaderObject,
+        HeaderObject, HeaderObjects,
+    },
+    remux::{drop_stale_indices, renumber},
+    Container,
+};
+use std::convert::TryInto;
+
+/// Combines the streams of `containers` into one file.
+///
+/// The file-level objects of the first file, such as its properties and
+/// descriptions, are kept. The streams of the others are renumbered to
+/// follow its streams, and their properties, codecs, bitrates, exclusions,
+/// priorities and metadata are moved over, with their languages added to the
+/// language list. If the audio streams are then in more than one language,
+/// they are made a language exclusion.
+///
+/// The payloads are interleaved by send time into new packets, as large as
+/// the largest of the files', and the files are lined up on the longest
+/// preroll. The durations are those of the longest file once lined up, or
+/// estimated from the packets if any of the files is a broadcast.
+pub fn merge<'a>(
+    mut containers: Vec<Container<'a>>,
+) -> Result<Container<'a>, Box<dyn std::error::Error>> {
+    if containers.is_empty() {
+        return Err("no files to merge".into());
+    }
+    let mut packet_len = 0;
+    let mut preroll = 0;
+    for container in containers.iter() {
+        let props = container
+            .header
+            .file_properties()
+            .ok_or("missing file properties object")?;
+        packet_len = packet_len.max(props.maximum_data_packet_size);
+        preroll = preroll.max(props.preroll);
+    }
+
+    let durations = durations(&containers, preroll)?;
+
+    let mut merged = containers.remove(0);
+    let mut payloads = Vec::new();
+    take_payloads(&mut merged, preroll, &mut payloads)?;
+    let mut next_number = merged
+        .streams()
+        .iter()
+        .map(|stream| stream.number + 1)
+        .max()
+        .unwrap_or(1);
+    for mut container in containers {
+        let map: Vec<(u16, u16)> = container
+            .streams()
+            .iter()
+            .map(|stream| stream.number)
+            .zip(next_number..)
+            .collect();
+        next_number += map.len() as u16;
+        renumber(&mut container, &map)?;
+        take_payloads(&mut container, preroll, &mut payloads)?;
+        move_streams(container.header, &mut merged.header)?;
+    }
+    exclude_languages(&mut merged.header);
+
+    let maximum_bitrate: u64 = merged
+        .streams()
+        .iter()
+        .filter_map(|stream| stream.bitrate)
+        .map(u64::from)
+        .sum();
+    let props = merged.header.file_properties_mut().unwrap();
+    props.preroll = preroll;
+    props.minimum_data_packet_size = packet_len;
+    props.maximum_data_packet_size = packet_len;
+    props.maximum_bitrate = maximum_bitrate.try_into().unwrap_or(u32::MAX);
+    match durations {
+        Some((play_duration, send_duration)) => {
+            props.play_duration = play_duration;
+            props.send_duration = send_duration;
+        }
+        None => props.flags |= BROADCAST_FLAG,
+    }
+
+    // A stable sort, so each file's payloads stay in order.
+    payloads.sort_by_key(|(send_time, _)| *send_time);
+    merged.data.packets = packetize(payloads, packet_len)?;
+    drop_stale_indices(&mut merged);
+    refresh(&mut merged)?;
+    Ok(merged)
+}
+
+/// The longest play and send durations of `containers` once they are lined
+/// up on `preroll`, or `None` if any of them is a broadcast, whose durations
+/// are placeholders.
+fn durations(
+    containers: &[Container],
+    preroll: u64,
+) -> Result<Option<(u64, u64)>, Box<dyn std::error::Error>> {
+    let mut durations = (0u64, 0u64);
+    for container in containers.iter() {
+        let props = container.header.file_properties().unwrap();
+        if props.is_broadcast() {
+            return Ok(None);
+        }
+        let delay = (preroll - props.preroll)
+            .checked_mul(10_000)
+            .ok_or("preroll is out of range")?;
+        let play_duration = props.play_duration.checked_add(delay);
+        let send_duration = props.send_duration.checked_add(delay);
+        durations.0 = durations
+            .0
+            .max(play_duration.ok_or("duration is out of range")?);
+        durations.1 = durations
+            .1
+            .max(send_duration.ok_or("duration is out of range")?);
+    }
+    Ok(Some(durations))
+}
+
+/// Takes the payloads out of `container`, delaying them by the difference
+/// between its preroll and `preroll`.
+fn take_payloads<'a>(
+    container: &mut Container<'a>,
+    preroll: u64,
+    payloads: &mut Vec<(u32, Payload<'a>)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let props = container.header.file_properties().unwrap();
+    let delay: u32 = (preroll - props.preroll).try_into()?;
+    let packets = std::mem::take(&mut container.data.packets);
+    for (send_time, mut payload) in unpack(packets) {
+        if let Some(time) = payload.presentation_time() {
+            let time = time
+                .checked_add(delay)
+                .ok_or("presentation time is out of range")?;
+            payload.set_presentation_time(time);
+        }
+        let send_time = send_time
+            .checked_add(delay)
+            .ok_or("send time is out of range")?;
+        payloads.push((send_time, payload));
+    }
+    Ok(())
+}
+
+/// Moves the objects about streams from `from` to `to`, merging their
+/// records into objects of the same kind where there can only be one.
+fn move_streams<'a>(
+    from: HeaderObjects<'a>,
+    to: &mut HeaderObjects<'a>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let languages = add_languages(&from, to)?;
+    let language = |index: u16| languages.get(usize::from(index)).copied().unwrap_or(index);
+
+    let mut properties_end = to
+        .objects
+        .iter()
+        .rposition(|object| matches!(object, HeaderObject::StreamProperties(_)))
+        .map_or(to.objects.len(), |index| index + 1);
+    for object in from.objects {
+        match object {
+            HeaderObject::StreamProperties(_) => {
+                to.objects.insert(properties_end, object);
+                properties_end += 1;
+            }
+            HeaderObject::CodecList(_)
+            | HeaderObject::StreamBitrateProperties(_)
+            | HeaderObject::BitrateMutualExclusion(_) => add_object(to, object),
+            HeaderObject::HeaderExtension(data) => {
+                for mut object in data.extension_objects {
+                    match &mut object {
+                        ExtensionHeaderObject::ExtendedStreamProperties(data) => {
+                            data.stream_language_id_index = language(data.stream_language_id_index);
+                            for name in data.stream_names.iter_mut() {
+                                name.language_id_index = language(name.language_id_index);
+                            }
+                        }
+                        // Records for stream 0 are about the whole file.
+                        ExtensionHeaderObject::Metadata(data) => data
+                            .description_records
+                            .retain(|record| record.stream_number != 0),
+                        ExtensionHeaderObject::MetadataLibrary(data) => {
+                            data.description_records
+                                .retain(|record| record.stream_number != 0);
+                            for record in data.description_records.iter_mut() {
+                                record.language_list_index = language(record.language_list_index);
+                            }
+                        }
+                        _ => {}
+                    }
+                    match object {
+                        ExtensionHeaderObject::ExtendedStreamProperties(_)
+                        | ExtensionHeaderObject::AdvancedMutualExclusion(_)
+                        | ExtensionHeaderObject::GroupMutualExclusion(_)
+                        | ExtensionHeaderObject::StreamPrioritization(_)
+                        | ExtensionHeaderObject::BandwidthSharing(_)
+                        | ExtensionHeaderObject::Metadata(_)
+                        | ExtensionHeaderObject::MetadataLibrary(_) => {
+                            add_extension_object(to, object)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Adds the languages of `from` to the language list of `to`, returning the
+/// new index of each.
+fn add_languages(
+    from: &HeaderObjects,
+    to: &mut HeaderObjects,
+) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    let count: u16 = from
+        .language_list()
+        .map_or(0, |list| list.language_id_records.len())
+        .try_into()?;
+    (0..count)
+        .map(|index| {
+            let tag = from.language(index).ok_or("unreadable language")?;
+            Ok(to.add_language(&tag).ok_or("language list is full")?)
+        })
+        .collect()
+}
+
+fn add_object<'a>(header: &mut HeaderObjects<'a>, mut object: HeaderObject<'a>) {
+    for existing in header.objects.iter_mut() {
+        match (existing, &mut object) {
+            (HeaderObject::CodecList(to), HeaderObject::CodecList(from)) => {
+                for entry in from.codec_entries.drain(..) {
+                    if !to.codec_entries.contains(&entry) {
+                        to.codec_entries.push(entry);
+                    }
+                }
+                return;
+            }
+            (
+                HeaderObject::StreamBitrateProperties(to),
+                HeaderObject::StreamBitrateProperties(from),
+            ) => {
+                to.bitrate_records.append(&mut from.bitrate_records);
+                return;
+            }
+            _ => {}
+        }
+    }
+    header.objects.push(object);
+}
+
+fn add_extension_object<'a>(header: &mut HeaderObjects<'a>, mut object: ExtensionHeaderObject<'a>) {
+    let extension = header.header_extension_mut();
+    for existing in extension.extension_objects.iter_mut() {
+        match (existing, &mut object) {
+            (
+                ExtensionHeaderObject::StreamPrioritization(to),
+                ExtensionHeaderObject::StreamPrioritization(from),
+            ) => {
+                to.priority_records.append(&mut from.priority_records);
+                return;
+            }
+            (ExtensionHeaderObject::Metadata(to), ExtensionHeaderObject::Metadata(from)) => {
+                to.description_records.append(&mut from.description_records);
+                return;
+            }
+            (
+                ExtensionHeaderObject::MetadataLibrary(to),
+                ExtensionHeaderObject::MetadataLibrary(from),
+            ) => {
+                to.description_records.append(&mut from.description_records);
+                return;
+            }
+            _ => {}
+        }
+    }
+    let empty = match &object {
+        ExtensionHeaderObject::Metadata(data) => data.description_records.is_empty(),
+        ExtensionHeaderObject::MetadataLibrary(data) => data.description_records.is_empty(),
+        _ => false,
+    };
+    if !empty {
+        extension.extension_objects.push(object);
+    }
+}
+
+/// Makes the audio streams a language exclusion if they are in more than one
+/// language, replacing the language exclusions between them. Streams in the
+/// same language are grouped together.
+fn exclude_languages(header: &mut HeaderObjects) {
+    let mut audio = Vec::new();
+    let mut languages: Vec<(String, Vec<u16>)> = Vec::new();
+    for stream in header.streams() {
+        if let (Some(AUDIO_MEDIA), Some(language)) = (stream.stream_type(), stream.language) {
+            audio.push(stream.number);
+            match languages
+                .iter_mut()
+                .find(|(tag, _)| tag.eq_ignore_ascii_case(&language))
+            {
+                Some((_, streams)) => streams.push(stream.number),
+                None => languages.push((language, vec![stream.number])),
+            }
+        }
+    }
+    if languages.len() < 2 {
+        return;
+    }
+
+    let extension = header.header_extension_mut();
+    extension.extension_objects.retain(|object| match object {
+        ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
+            data.exclusion_type != MUTEX_LANGUAGE
+                || !data
+                    .stream_numbers
+                    .iter()
+                    .any(|number| audio.contains(number))
+        }
+        ExtensionHeaderObject::GroupMutualExclusion(data) => {
+            data.exclusion_type != MUTEX_LANGUAGE
+                || !data
+                    .records
+                    .iter()
+                    .flatten()
+                    .any(|number| audio.contains(number))
+        }
+        _ => true,
+    });
+    let object = if languages.iter().all(|(_, streams)| streams.len() == 1) {
+        ExtensionHeaderObject::AdvancedMutualExclusion(AdvancedMutualExclusionData {
+            exclusion_type: MUTEX_LANGUAGE,
+            stream_numbers: languages
+                .into_iter()
+                .map(|(_, streams)| streams[0])
+                .collect(),
+        })
+    } else {
+        ExtensionHeaderObject::GroupMutualExclusion(GroupMutualExclusionData {
+            exclusion_type: MUTEX_LANGUAGE,
+            records: languages.into_iter().map(|(_, streams)| streams).collect(),
+        })
+    };
+    extension.extension_objects.push(object);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        header::extended_stream_properties::ExtendedStreamPropertiesData, remux,
+        test_support::extended_stream_properties,
+    };
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    /// The audio of the sample, as a file of its own in `language`.
+    fn audio(language: &str) -> Container<'static> {
+        let container = crate::parse(BASIC_WMV).unwrap().into_owned();
+        let mut container = remux::drop_streams(container, &[2]).unwrap();
+        let header = &mut container.header;
+        let stream_language_id_index = header.add_language(language).unwrap();
+        let extended = ExtendedStreamPropertiesData {
+            stream_language_id_index,
+            ..extended_stream_properties(1)
+        };
+        header
+            .header_extension_mut()
+            .extension_objects
+            .push(ExtensionHeaderObject::ExtendedStreamProperties(extended));
+        container
+    }
+
+    #[test]
+    fn merge_languages() {
+        let video = crate::parse(BASIC_WMV).unwrap();
+        let video = remux::drop_streams(video, &[1]).unwrap();
+        let original = video.header.file_properties().unwrap().clone();
+        let merged = merge(vec![video, audio("en-us"), audio("fr-fr")]).expect("to merge");
+        let mut out = Vec::new();
+        merged.write(&mut out).unwrap();
+        let merged = crate::parse(&out).unwrap();
+
+        let streams = merged.streams();
+        let numbers: Vec<u16> = streams.iter().map(|stream| stream.number).collect();
+        assert_eq!(numbers, vec![2, 3, 4]);
+        assert_eq!(streams[1].language.as_deref(), Some("en-us"));
+        assert_eq!(streams[2].language.as_deref(), Some("fr-fr"));
+        assert!(streams.iter().all(|stream| stream.issues.is_empty()));
+        assert!(streams.iter().all(|stream| stream.bitrate.is_some()));
+
+        let exclusion = merged
+            .header
+            .extension_objects()
+            .find_map(|object| match object {
+                ExtensionHeaderObject::AdvancedMutualExclusion(data) => Some(data),
+                _ => None,
+            });
+        assert_eq!(exclusion.unwrap().stream_numbers, vec![3, 4]);
+        let codecs = merged
+            .header
+            .objects
+            .iter()
+            .find_map(|object| match object {
+                HeaderObject::CodecList(data) => Some(data.codec_entries.len()),
+                _ => None,
+            });
+        assert_eq!(codecs, Some(2));
+
+        let payloads = merged
+            .data
+            .packets
+            .iter()
+            .flat_map(|packet| packet.payload.payloads());
+        let mut counts = [0; 5];
+        for payload in payloads {
+            counts[payload.stream_flags().stream_number as usize] += 1;
+        }
+        assert_eq!(counts[0..2], [0, 0]);
+        assert_eq!(counts[3], counts[4]);
+        let send_times: Vec<u32> = merged
+            .data
+            .packets
+            .iter()
+            .map(|packet| packet.payload_parsing_data.send_time)
+            .collect();
+        assert!(send_times.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let props = merged.header.file_properties().unwrap();
+        assert_eq!(props.play_duration, original.play_duration);
+        assert_eq!(props.send_duration, original.send_duration);
+    }
+
+    #[test]
+    fn merge_out_of_range_delay() {
+        let video = crate::parse(BASIC_WMV).unwrap();
+        let video = remux::drop_streams(video, &[1]).unwrap();
+        let mut audio = audio("en-us");
+        audio.header.file_properties_mut().unwrap().preroll = u32::MAX.into();
+        assert!(merge(vec![video, audio]).is_err());
+    }
+}
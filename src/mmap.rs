@@ -0,0 +1,57 @@
+//! Parsing straight from a memory-mapped file, so only the pages that are
+//! actually read are loaded.
+
+use crate::{error::AsfError, options::ParseOptions, Container};
+use memmap2::Mmap;
+use std::{fs::File, io, ops::Deref, path::Path};
+
+/// A memory-mapped ASF file. Containers parsed from it borrow the mapping.
+pub struct MappedFile {
+    map: Mmap,
+}
+
+/// Maps the file at `path` for parsing.
+///
+/// The file must not be modified while it is mapped. Writes from other
+/// processes would show up in the parsed data, and truncating the file can
+/// crash the process.
+pub fn open_mmap<P: AsRef<Path>>(path: P) -> io::Result<MappedFile> {
+    let file = File::open(path)?;
+    // Safety: see above. There is no way to prevent outside changes to the
+    // file, so avoiding them is left to the caller.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(MappedFile { map })
+}
+
+impl MappedFile {
+    pub fn parse(&self) -> Result<Container<'_>, AsfError> {
+        crate::parse(&self.map)
+    }
+
+    pub fn parse_with_options(&self, options: &ParseOptions) -> Result<Container<'_>, AsfError> {
+        crate::parse_with_options(&self.map, options)
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mapped_file() {
+        let file = open_mmap("samples/basic.wmv").expect("to map the file");
+        let container = file.parse().expect("to parse successfully");
+        assert_eq!(
+            container,
+            crate::parse(include_bytes!("../samples/basic.wmv")).unwrap()
+        );
+    }
+}
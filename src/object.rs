@@ -1,5 +1,9 @@
-use crate::{guid::*, span::Span};
-use nom::{bytes::streaming::take, error::ParseError, number::streaming::le_u64, IResult};
+use crate::{
+    error::{in_object, AsfError, ParseError},
+    guid::*,
+    span::Span,
+};
+use nom::{bytes::streaming::take, number::streaming::le_u64, Err, IResult, Slice};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
@@ -22,9 +26,16 @@ pub fn object_header<'a, E: ParseError<Span<'a>>>(
     Ok((input, ObjectHeader { guid, size }))
 }
 
-pub fn object<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Object, E> {
+pub fn object<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>, Object<'a>, E> {
+    let start = input;
     let (input, header) = object_header(input)?;
-    let (input, data) = take(header.size - 24)(input)?;
+    let data_len = header.size.checked_sub(24).ok_or_else(|| {
+        // Point at the size field rather than past it.
+        let size_input = start.slice(16..);
+        let error = Err::Error(E::from_size_mismatch(size_input, 24, header.size));
+        in_object(size_input, header.guid)(error)
+    })?;
+    let (input, data) = take(data_len)(input)?;
     Ok((
         input,
         Object {
@@ -33,3 +44,19 @@ pub fn object<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> IResult<Span<'a>,
         },
     ))
 }
+
+/// Takes the rest of `input` as the body of an object whose size is unusable,
+/// along with why, so a lenient parse can keep the bytes. The GUID is nil if
+/// it was cut off too.
+pub(crate) fn unsized_object(input: Span) -> (Uuid, Span, AsfError) {
+    let error = match object::<AsfError>(input) {
+        Err(error) => AsfError::from_err(error, input),
+        Ok(_) => AsfError::InvalidValue {
+            offset: input.location_offset(),
+            path: Vec::new(),
+        },
+    };
+    let guid = guid::<_, AsfError>(input).map_or(Uuid::nil(), |(_, guid)| guid);
+    let data = input.slice(input.len().min(24)..);
+    (guid, data, error)
+}
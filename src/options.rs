@@ -0,0 +1,179 @@
+use crate::span::Span;
+use nom::{
+    error::{ErrorKind, ParseError},
+    Err,
+};
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Options controlling how forgiving the parser is of damaged files.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Skip over damaged data packets instead of failing. Each damaged byte
+    /// range is recorded in `DataObject::damaged`.
+    pub recover: bool,
+    /// Keep header and index objects whose bodies fail to parse as
+    /// `HeaderObject::Invalid` or `IndexObject::Invalid` and carry on with
+    /// the next object, instead of failing the whole file.
+    pub lenient: bool,
+    /// Caps on how much a single file may make the parser allocate.
+    pub limits: Limits,
+}
+
+/// Resource limits for parsing untrusted files. Exceeding any of them stops
+/// parsing with an `ErrorKind::TooLarge` failure. The defaults are unlimited.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Limits {
+    pub max_header_objects: usize,
+    /// Descriptors in extended content description, metadata and metadata
+    /// library objects, counted per object.
+    pub max_descriptors: usize,
+    pub max_markers: usize,
+    pub max_script_commands: usize,
+    pub max_packets: usize,
+    pub max_payloads_per_packet: usize,
+    /// Total bytes of parsed objects, packets and payloads held in memory.
+    /// Borrowed input is not counted.
+    pub max_allocated_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_header_objects: usize::MAX,
+            max_descriptors: usize::MAX,
+            max_markers: usize::MAX,
+            max_script_commands: usize::MAX,
+            max_packets: usize::MAX,
+            max_payloads_per_packet: usize::MAX,
+            max_allocated_bytes: usize::MAX,
+        }
+    }
+}
+
+/// Tracks the limits for one parse, including how many bytes may still be
+/// allocated.
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    pub limits: Limits,
+    allocated: AtomicUsize,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            allocated: AtomicUsize::new(0),
+        }
+    }
+
+    /// Accounts for `count` more values of `T`, failing if that would go over
+    /// `max` of them or over the allocation limit.
+    pub fn reserve<'a, T, E: ParseError<Span<'a>>>(
+        &self,
+        input: Span<'a>,
+        count: usize,
+        max: usize,
+    ) -> Result<(), Err<E>> {
+        if count > max {
+            return Err(limit_exceeded(input));
+        }
+        let bytes = count
+            .checked_mul(size_of::<T>())
+            .ok_or_else(|| limit_exceeded(input))?;
+        self.allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                allocated
+                    .checked_add(bytes)
+                    .filter(|&allocated| allocated <= self.limits.max_allocated_bytes)
+            })
+            .map_err(|_| limit_exceeded(input))?;
+        Ok(())
+    }
+
+    /// How many bytes have been accounted for so far.
+    #[cfg(feature = "rayon")]
+    pub fn allocated(&self) -> usize {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    /// Forgets allocations made since `allocated()` returned `allocated`.
+    #[cfg(feature = "rayon")]
+    pub fn restore(&self, allocated: usize) {
+        self.allocated.store(allocated, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn limit_exceeded<'a, E: ParseError<Span<'a>>>(input: Span<'a>) -> Err<E> {
+    Err::Failure(E::from_error_kind(input, ErrorKind::TooLarge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::AsfError, Container};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    fn limit_exceeded(options: &ParseOptions) -> bool {
+        matches!(
+            Container::parse_with_options::<AsfError>(Span::new(BASIC_WMV), options),
+            Err(nom::Err::Failure(AsfError::LimitExceeded { .. }))
+        )
+    }
+
+    #[test]
+    fn parse_limits() {
+        let (_, container) =
+            Container::parse::<AsfError>(Span::new(BASIC_WMV)).expect("to parse successfully");
+        let packets = container.data.packets.len();
+        let header_objects = container.header.objects.len();
+
+        let limited = |limits: Limits| ParseOptions {
+            limits,
+            ..Default::default()
+        };
+        let exact = Limits {
+            max_packets: packets,
+            max_header_objects: header_objects,
+            ..Default::default()
+        };
+        assert!(!limit_exceeded(&limited(exact)));
+        assert!(limit_exceeded(&limited(Limits {
+            max_packets: packets - 1,
+            ..Default::default()
+        })));
+        assert!(limit_exceeded(&limited(Limits {
+            max_header_objects: header_objects - 1,
+            ..Default::default()
+        })));
+        assert!(limit_exceeded(&limited(Limits {
+            max_descriptors: 0,
+            ..Default::default()
+        })));
+        assert!(limit_exceeded(&limited(Limits {
+            max_allocated_bytes: 4096,
+            ..Default::default()
+        })));
+
+        // Limits are not damage, so recovery and lenient parsing still stop.
+        assert!(limit_exceeded(&ParseOptions {
+            recover: true,
+            lenient: true,
+            limits: Limits {
+                max_packets: packets - 1,
+                ..Default::default()
+            },
+        }));
+        assert!(limit_exceeded(&ParseOptions {
+            lenient: true,
+            limits: Limits {
+                max_descriptors: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+    }
+}
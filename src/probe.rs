@@ -0,0 +1,126 @@
+//! Reading a file's header without its packets, for when only the
+//! properties, streams and tags are wanted.
+
+use crate::{
+    header::HeaderObjects,
+    options::ParseOptions,
+    push::{Event, PushParser},
+    sniff::MediaType,
+};
+use std::io::{self, Read};
+use uuid::Uuid;
+
+/// The header object and the start of the data object.
+#[derive(Debug, PartialEq)]
+pub struct Probe {
+    pub header: HeaderObjects<'static>,
+    pub file_id: Uuid,
+    pub total_data_packets: u64,
+}
+
+impl Probe {
+    pub fn media_type(&self) -> MediaType {
+        self.header.media_type()
+    }
+}
+
+/// Reads the header object and the data object's preamble from `reader`,
+/// stopping before the first packet. A header that fails to parse is
+/// returned as an `InvalidData` error with the `AsfError` inside.
+pub fn probe<R: Read>(reader: R) -> io::Result<Probe> {
+    probe_with_options(reader, &ParseOptions::default())
+}
+
+pub fn probe_with_options<R: Read>(mut reader: R, options: &ParseOptions) -> io::Result<Probe> {
+    let mut parser = PushParser::new(options);
+    let header = match next_event(&mut parser, &mut reader)? {
+        Event::Header(header) => header,
+        _ => unreachable!("the header is always the first event"),
+    };
+    match next_event(&mut parser, &mut reader)? {
+        Event::Data {
+            file_id,
+            total_data_packets,
+            ..
+        } => Ok(Probe {
+            header,
+            file_id,
+            total_data_packets,
+        }),
+        _ => unreachable!("the data object always follows the header"),
+    }
+}
+
+/// Reads exactly as much as `parser` needs for its next event, a piece at a
+/// time. The parser refuses an object too large for the limits as soon as its
+/// size arrives, before the rest of it is read.
+fn next_event<R: Read>(parser: &mut PushParser, reader: &mut R) -> io::Result<Event> {
+    let mut chunk = Vec::new();
+    loop {
+        let event = parser
+            .next_event()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        if let Some(event) = event {
+            return Ok(event);
+        }
+        chunk.resize(parser.read_len(), 0);
+        let len = match reader.read(&mut chunk) {
+            Ok(len) => len,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if len == 0 {
+            parser.end();
+        }
+        parser.push(&chunk[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::AsfError, options::Limits};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[test]
+    fn probe_header() {
+        let mut reader = BASIC_WMV;
+        let probe = probe(&mut reader).expect("to probe successfully");
+        let container = crate::parse(BASIC_WMV).unwrap();
+        assert_eq!(probe.header, container.header);
+        assert_eq!(probe.file_id, container.data.file_id);
+        assert_eq!(probe.total_data_packets, container.data.total_data_packets);
+
+        // Nothing past the data object's preamble was read.
+        let read = BASIC_WMV.len() - reader.len();
+        assert_eq!(read, container.header.size_of() + 50);
+
+        let error = super::probe(&BASIC_WMV[..read - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn probe_limits() {
+        // A header claiming far more than the limit fails from its size alone.
+        let mut header = BASIC_WMV[..24].to_vec();
+        header[16..24].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let stream_len = 64 << 20;
+        let mut reader = (&header[..]).chain(io::repeat(0).take(stream_len));
+        let options = ParseOptions {
+            limits: Limits {
+                max_allocated_bytes: 1 << 20,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = probe_with_options(&mut reader, &options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = error
+            .into_inner()
+            .and_then(|error| error.downcast::<AsfError>().ok())
+            .expect("an AsfError");
+        assert!(matches!(*error, AsfError::LimitExceeded { offset: 16, .. }));
+        assert_eq!(reader.get_ref().1.limit(), stream_len);
+    }
+}
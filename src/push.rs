@@ -0,0 +1,554 @@
+//! A push parser for files that arrive in pieces, such as from a socket.
+
+use crate::{
+    combinators::guid_tag,
+    data::{at_top_level_object, overrun, packets::DataPacket},
+    error::{AsfError, ParseError},
+    guid::*,
+    header::HeaderObjects,
+    index::IndexObject,
+    object::object_header,
+    options::{limit_exceeded, Budget, ParseOptions},
+    span::Span,
+};
+use nom::{
+    error::context,
+    number::streaming::{le_u16, le_u64},
+    Err, IResult, Slice,
+};
+use std::convert::TryInto;
+use uuid::Uuid;
+
+/// The most `PushParser::read_len` asks for at once.
+const MAX_READ_LEN: usize = 64 * 1024;
+
+/// Something read by a `PushParser`.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// The complete header object.
+    Header(HeaderObjects<'static>),
+    /// The start of the data object, before its packets.
+    Data {
+        file_id: Uuid,
+        total_data_packets: u64,
+        reserved: u16,
+    },
+    Packet(DataPacket<'static>),
+    Index(IndexObject<'static>),
+    /// The input ended after a complete file.
+    End,
+}
+
+#[derive(Debug)]
+enum State {
+    Header,
+    DataHeader {
+        packet_len: u32,
+        broadcast: bool,
+    },
+    /// `packet_len` is the size of every packet, or 0 if each packet gives
+    /// its own. `remaining` is the size left in the data object, or `None`
+    /// for a broadcast, whose packets run until the next object or the end.
+    Packets {
+        packet_len: u32,
+        remaining: Option<u64>,
+    },
+    Indices,
+    Done,
+}
+
+/// Parses a file fed to it in chunks of any size. Only the object or packet
+/// being read is buffered; everything else is handed out as an `Event`.
+///
+/// Limits apply to each object and packet on its own, since nothing is kept
+/// after it is returned. An object or packet claiming more than
+/// `max_allocated_bytes` is refused before it is buffered. Damaged packets
+/// are errors, as in a strict parse.
+#[derive(Debug)]
+pub struct PushParser {
+    options: ParseOptions,
+    state: State,
+    buffer: Vec<u8>,
+    /// Where the unread part of `buffer` starts. Read bytes are only dropped
+    /// when more input arrives.
+    start: usize,
+    /// The file offset of the unread part of `buffer`.
+    offset: usize,
+    ended: bool,
+}
+
+impl Default for PushParser {
+    fn default() -> Self {
+        Self::new(&ParseOptions::default())
+    }
+}
+
+impl PushParser {
+    pub fn new(options: &ParseOptions) -> Self {
+        PushParser {
+            options: *options,
+            state: State::Header,
+            buffer: Vec::new(),
+            start: 0,
+            offset: 0,
+            ended: false,
+        }
+    }
+
+    /// Adds the next chunk of input.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Marks the end of the input. Whatever is still buffered must complete
+    /// the file.
+    pub fn end(&mut self) {
+        self.ended = true;
+    }
+
+    /// How many more bytes `next_event` needs before it can return another
+    /// event, or `None` if that is not known until they are read.
+    pub fn needed(&self) -> Option<usize> {
+        let wanted = match self.state {
+            State::Header | State::Indices => self.object_len().unwrap_or(24),
+            State::DataHeader { .. } => 50,
+            State::Packets {
+                remaining: Some(0), ..
+            }
+            | State::Done => 0,
+            State::Packets {
+                packet_len,
+                remaining,
+            } => {
+                let packet_len = self.packet_len(packet_len)?;
+                remaining.map_or(packet_len, |remaining| {
+                    remaining.min(packet_len as u64) as usize
+                })
+            }
+        };
+        Some(wanted.saturating_sub(self.buffered().len()))
+    }
+
+    /// How many bytes a reader should read next: what `needed` asks for, at
+    /// most 64 KiB at a time, or 64 KiB when that isn't known.
+    pub fn read_len(&self) -> usize {
+        self.needed()
+            .map_or(MAX_READ_LEN, |needed| needed.clamp(1, MAX_READ_LEN))
+    }
+
+    /// The file offset of the next byte `next_event` will read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Skips the rest of the data object's packets, for when only the header
+    /// and the index objects are wanted. Returns how many bytes following the
+    /// input pushed so far belong to the skipped packets, for the caller to
+    /// skip before pushing more. Returns `None`, skipping nothing, before the
+    /// data object starts or when its size isn't known, as in a broadcast.
+    pub fn skip_packets(&mut self) -> Option<u64> {
+        match self.state {
+            State::Packets {
+                remaining: Some(remaining),
+                ..
+            } => {
+                let buffered = remaining.min(self.buffered().len() as u64) as usize;
+                self.consume(buffered);
+                let rest = remaining - buffered as u64;
+                self.offset = self
+                    .offset
+                    .saturating_add(rest.try_into().unwrap_or(usize::MAX));
+                self.state = State::Indices;
+                Some(rest)
+            }
+            State::Indices | State::Done => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Returns the next event, or `None` if more input is needed first.
+    pub fn next_event(&mut self) -> Result<Option<Event>, AsfError> {
+        loop {
+            match self.state {
+                State::Header => {
+                    let len = match self.object_len_within_limits()? {
+                        Some(len) if self.buffered().len() >= len => len,
+                        _ => return self.starved(),
+                    };
+                    let input = Span::new(&self.buffered()[..len]);
+                    let budget = Budget::new(self.options.limits);
+                    let header = HeaderObjects::parse_with(input, &self.options, &budget)
+                        .map_err(|error| self.error(error, input))?
+                        .1
+                        .into_owned();
+                    let props = header.file_properties();
+                    self.state = State::DataHeader {
+                        packet_len: props.map_or(0, |props| props.maximum_data_packet_size),
+                        broadcast: props.is_some_and(|props| props.is_broadcast()),
+                    };
+                    self.consume(len);
+                    return Ok(Some(Event::Header(header)));
+                }
+                State::DataHeader {
+                    packet_len,
+                    broadcast,
+                } => {
+                    if self.buffered().len() < 50 {
+                        return self.starved();
+                    }
+                    let input = Span::new(&self.buffered()[..50]);
+                    let (size, file_id, total_data_packets, reserved) = data_header(input)
+                        .map_err(|error| self.error(error, input))?
+                        .1;
+                    let remaining = if broadcast {
+                        None
+                    } else {
+                        let len = size.checked_sub(50).ok_or_else(|| {
+                            let error = AsfError::from_size_mismatch(input.slice(16..), 50, size);
+                            error.offset_by(self.offset)
+                        })?;
+                        Some(len)
+                    };
+                    self.state = State::Packets {
+                        packet_len,
+                        remaining,
+                    };
+                    self.consume(50);
+                    return Ok(Some(Event::Data {
+                        file_id,
+                        total_data_packets,
+                        reserved,
+                    }));
+                }
+                State::Packets {
+                    packet_len,
+                    remaining,
+                } => {
+                    let buffered = self.buffered().len();
+                    let at_end = match remaining {
+                        Some(remaining) => remaining == 0,
+                        None if buffered < 16 => self.ended && buffered == 0,
+                        None => at_top_level_object(Span::new(self.buffered())),
+                    };
+                    if at_end {
+                        self.state = State::Indices;
+                        continue;
+                    }
+                    if remaining.is_none() && buffered < 16 {
+                        return self.starved();
+                    }
+                    let available = match remaining {
+                        Some(remaining) => buffered.min(remaining as usize),
+                        None => buffered,
+                    };
+                    let input = Span::new(&self.buffered()[..available]);
+                    // The packet's size is known from the file properties, or
+                    // from its own length field once that has arrived.
+                    let size = self.packet_len(packet_len);
+                    if size.is_some_and(|size| size > self.options.limits.max_allocated_bytes) {
+                        let field = DataPacket::length_field(input).map(|(field, _)| field);
+                        return Err(self.limit_exceeded(field.unwrap_or(input)));
+                    }
+                    // Packets are parsed as if all of their input has arrived, so
+                    // wait for all of it, or for the end when it is not known.
+                    let more = !self.ended && remaining.is_none_or(|len| len > available as u64);
+                    if more && size.is_some_and(|size| available < size) {
+                        return Ok(None);
+                    }
+                    let budget = Budget::new(self.options.limits);
+                    // Once the packet's whole size or the rest of the data
+                    // object is here, needing more means the packet is damaged.
+                    let overran = size.is_some_and(|size| available >= size)
+                        || remaining.is_some_and(|len| len <= available as u64);
+                    let (rest, packet) =
+                        match DataPacket::parse_with(input, packet_len.into(), &budget) {
+                            Ok(result) => result,
+                            Err(Err::Incomplete(needed)) if overran => {
+                                let error: AsfError = overrun(input, needed);
+                                return Err(error.offset_by(self.offset));
+                            }
+                            Err(Err::Incomplete(_)) if more => return Ok(None),
+                            Err(error) => return Err(self.error(error, input)),
+                        };
+                    let packet = packet.into_owned();
+                    let len = available - rest.len();
+                    self.state = State::Packets {
+                        packet_len,
+                        remaining: remaining.map(|remaining| remaining - len as u64),
+                    };
+                    self.consume(len);
+                    return Ok(Some(Event::Packet(packet)));
+                }
+                State::Indices => {
+                    if self.ended && self.buffered().is_empty() {
+                        self.state = State::Done;
+                        return Ok(Some(Event::End));
+                    }
+                    let len = match self.object_len_within_limits()? {
+                        Some(len) if self.buffered().len() >= len => len,
+                        _ => return self.starved(),
+                    };
+                    let input = Span::new(&self.buffered()[..len]);
+                    let index = IndexObject::parse(input)
+                        .map_err(|error| self.error(error, input))?
+                        .1
+                        .into_owned();
+                    self.consume(len);
+                    return Ok(Some(Event::Index(index)));
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    /// The input not read yet.
+    fn buffered(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+
+    /// The size of the buffered object, once its header has arrived.
+    fn object_len(&self) -> Option<usize> {
+        let (_, header) = object_header::<nom::error::Error<_>>(Span::new(self.buffered())).ok()?;
+        Some(header.size.try_into().unwrap_or(usize::MAX).max(24))
+    }
+
+    /// Like `object_len`, but fails if the object is too large to buffer.
+    fn object_len_within_limits(&self) -> Result<Option<usize>, AsfError> {
+        match self.object_len() {
+            Some(len) if len > self.options.limits.max_allocated_bytes => {
+                let size = Span::new(self.buffered()).slice(16..);
+                Err(self.limit_exceeded(size))
+            }
+            len => Ok(len),
+        }
+    }
+
+    /// The size of the buffered packet: the size of every packet, if they
+    /// all have the same, or else the one in its length field.
+    fn packet_len(&self, packet_len: u32) -> Option<usize> {
+        let packet_len = match packet_len {
+            0 => DataPacket::length_field(Span::new(self.buffered()))?.1,
+            packet_len => packet_len,
+        };
+        Some(packet_len as usize)
+    }
+
+    fn starved(&self) -> Result<Option<Event>, AsfError> {
+        match self.ended {
+            true => Err(AsfError::Truncated {
+                offset: self.offset + self.buffered().len(),
+                path: Vec::new(),
+            }),
+            false => Ok(None),
+        }
+    }
+
+    fn error(&self, error: Err<AsfError>, input: Span) -> AsfError {
+        AsfError::from_err(error, input).offset_by(self.offset)
+    }
+
+    fn limit_exceeded(&self, input: Span) -> AsfError {
+        self.error(limit_exceeded(input), input)
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+        self.offset += len;
+    }
+}
+
+fn data_header(input: Span) -> IResult<Span, (u64, Uuid, u64, u16), AsfError> {
+    context("data", |input| {
+        let (input, _data_object_guid) = guid_tag(DATA_OBJECT)(input)?;
+        let (input, size) = le_u64(input)?;
+        let (input, file_id) = guid(input)?;
+        let (input, total_data_packets) = le_u64(input)?;
+        let (input, reserved) = le_u16(input)?;
+        Ok((input, (size, file_id, total_data_packets, reserved)))
+    })(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        options::Limits,
+        test_support::{find_guid, variable_size_wmv},
+    };
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    fn events(buf: &[u8], chunk_len: usize) -> Vec<Event> {
+        let mut parser = PushParser::default();
+        let mut events = Vec::new();
+        for chunk in buf.chunks(chunk_len) {
+            parser.push(chunk);
+            while let Some(event) = parser.next_event().expect("to parse successfully") {
+                events.push(event);
+            }
+        }
+        parser.end();
+        while let Some(event) = parser.next_event().expect("to parse successfully") {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn push_chunks() {
+        let container = crate::parse(BASIC_WMV).unwrap();
+        for &chunk_len in [1, 7, 1289, 4096, BASIC_WMV.len()].iter() {
+            let events = events(BASIC_WMV, chunk_len);
+            match &events[0] {
+                Event::Header(header) => assert_eq!(*header, container.header),
+                event => panic!("expected the header, got {:?}", event),
+            }
+            assert_eq!(
+                events[1],
+                Event::Data {
+                    file_id: container.data.file_id,
+                    total_data_packets: container.data.total_data_packets,
+                    reserved: container.data.reserved,
+                }
+            );
+            let packets: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Packet(packet) => Some(packet),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(packets.len(), container.data.packets.len());
+            assert!(packets
+                .iter()
+                .zip(container.data.packets.iter())
+                .all(|(a, b)| *a == b));
+            let indices: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Index(index) => Some(index),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(indices.len(), container.indices.objects.len());
+            assert_eq!(events.last(), Some(&Event::End));
+        }
+    }
+
+    #[test]
+    fn push_variable_size_packets() {
+        let (buf, sizes) = variable_size_wmv();
+        let container = crate::parse(&buf).unwrap();
+        assert_eq!(container.data.packets.len(), sizes.len());
+        for &chunk_len in [1, 100, 4096, buf.len()].iter() {
+            let events = events(&buf, chunk_len);
+            let packets: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Packet(packet) => Some(packet),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(packets.len(), sizes.len());
+            assert!(packets
+                .iter()
+                .zip(container.data.packets.iter())
+                .all(|(a, b)| *a == b));
+            assert_eq!(events.last(), Some(&Event::End));
+        }
+    }
+
+    #[test]
+    fn push_damaged_packet() {
+        // Overwrite the header of the third packet.
+        let packet = find_guid(BASIC_WMV, DATA_OBJECT) + 50 + 1289 * 2;
+        let mut buf = BASIC_WMV.to_vec();
+        buf[packet..packet + 16].fill(0xff);
+
+        for &chunk_len in [1, 1289, 4096].iter() {
+            let mut parser = PushParser::default();
+            let mut pushed = 0;
+            let error = 'outer: loop {
+                let chunk = &buf[pushed..(pushed + chunk_len).min(buf.len())];
+                assert!(!chunk.is_empty(), "expected the damaged packet to fail");
+                parser.push(chunk);
+                pushed += chunk.len();
+                loop {
+                    match parser.next_event() {
+                        Ok(Some(_)) => {}
+                        Ok(None) => break,
+                        Err(error) => break 'outer error,
+                    }
+                }
+                assert_ne!(parser.needed(), Some(0));
+            };
+            // The error comes as soon as the packet is in, at its length field.
+            assert!(pushed < packet + 1289 + chunk_len);
+            assert!(matches!(error, AsfError::SizeMismatch { .. }));
+            assert_eq!(error.offset(), packet + 3 + 2);
+        }
+    }
+
+    #[test]
+    fn push_needed() {
+        let mut parser = PushParser::default();
+        assert_eq!(parser.needed(), Some(24));
+        parser.push(&BASIC_WMV[..24]);
+        assert_eq!(parser.next_event(), Ok(None));
+        let header_len = u64::from_le_bytes(BASIC_WMV[16..24].try_into().unwrap()) as usize;
+        assert_eq!(parser.needed(), Some(header_len - 24));
+        parser.push(&BASIC_WMV[24..header_len + 10]);
+        assert!(matches!(parser.next_event(), Ok(Some(Event::Header(_)))));
+        assert_eq!(parser.needed(), Some(40));
+
+        parser.end();
+        assert_eq!(
+            parser.next_event(),
+            Err(AsfError::Truncated {
+                offset: header_len + 10,
+                path: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn push_limits() {
+        let options = ParseOptions {
+            limits: Limits {
+                max_allocated_bytes: 1 << 20,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // A header claiming far more than the limit is refused from its size.
+        let mut parser = PushParser::new(&options);
+        let mut buf = BASIC_WMV[..24].to_vec();
+        buf[16..24].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        parser.push(&buf);
+        let error = parser.next_event().expect_err("expected the size to fail");
+        assert!(matches!(error, AsfError::LimitExceeded { .. }));
+        assert_eq!(error.offset(), 16);
+
+        // So is a packet claiming more than the limit.
+        let (mut buf, _) = variable_size_wmv();
+        let packet = find_guid(&buf, DATA_OBJECT) + 50;
+        let options = ParseOptions {
+            limits: Limits {
+                max_allocated_bytes: 0x8000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut parser = PushParser::new(&options);
+        buf[packet + 5..packet + 7].fill(0xff);
+        parser.push(&buf[..packet + 16]);
+        assert!(matches!(parser.next_event(), Ok(Some(Event::Header(_)))));
+        assert!(matches!(parser.next_event(), Ok(Some(Event::Data { .. }))));
+        let error = parser.next_event().expect_err("expected the size to fail");
+        assert!(matches!(error, AsfError::LimitExceeded { .. }));
+        assert_eq!(error.offset(), packet + 5);
+    }
+}
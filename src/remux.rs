@@ -0,0 +1,483 @@
+//! Rewriting files with only some of their streams, such as one rendition of
+//! a multiple bitrate file.
+
+use crate::{
+    data::packetize::{packetize, unpack},
+    finalize::refresh,
+    guid::*,
+    header::{header_extension::ExtensionHeaderObject, HeaderObject, HeaderObjects},
+    index::IndexObject,
+    select::Constraints,
+    Container,
+};
+use std::convert::TryInto;
+
+/// Makes a file with the streams `select_streams` picks under `constraints`.
+/// See `keep_streams`.
+pub fn extract<'a>(
+    container: Container<'a>,
+    constraints: &Constraints,
+) -> Result<Container<'a>, Box<dyn std::error::Error>> {
+    let selection = container.select_streams(constraints);
+    keep_streams(container, &selection.streams)
+}
+
+/// Makes a file with only the streams numbered in `streams`, keeping their
+/// numbers.
+///
+/// The other streams' properties, codec list entries, bitrates, priorities,
+/// metadata and payloads are dropped, and exclusions, bandwidth sharing and
+/// index parameters are narrowed to the kept streams. The packets are
+/// rebuilt at the same packet size, and the simple indices regenerated;
+/// other index objects would be out of date and are dropped. The durations
+/// stay as they were, unless the file is a broadcast.
+pub fn keep_streams<'a>(
+    mut container: Container<'a>,
+    streams: &[u16],
+) -> Result<Container<'a>, Box<dyn std::error::Error>> {
+    retain_streams(&mut container, streams)?;
+    refresh(&mut container)?;
+    Ok(container)
+}
+
+/// Makes a file without the streams numbered in `streams`. See
+/// `keep_streams`.
+pub fn drop_streams<'a>(
+    container: Container<'a>,
+    streams: &[u16],
+) -> Result<Container<'a>, Box<dyn std::error::Error>> {
+    let kept: Vec<u16> = container
+        .streams()
+        .iter()
+        .map(|stream| stream.number)
+        .filter(|number| !streams.contains(number))
+        .collect();
+    keep_streams(container, &kept)
+}
+
+/// Makes a file with the streams numbered in `streams`, in that order and
+/// renumbered from 1. See `keep_streams` and `renumber`.
+pub fn remux<'a>(
+    mut container: Container<'a>,
+    streams: &[u16],
+) -> Result<Container<'a>, Box<dyn std::error::Error>> {
+    let numbers: Vec<u16> = container
+        .streams()
+        .iter()
+        .map(|stream| stream.number)
+        .collect();
+    for (i, number) in streams.iter().enumerate() {
+        if !numbers.contains(number) {
+            return Err(format!("no stream {}", number).into());
+        }
+        if streams[..i].contains(number) {
+            return Err(format!("stream {} is listed twice", number).into());
+        }
+    }
+
+    retain_streams(&mut container, streams)?;
+    let map: Vec<(u16, u16)> = streams.iter().copied().zip(1..).collect();
+    renumber(&mut container, &map)?;
+    for object in container.header.objects.iter_mut() {
+        match object {
+            HeaderObject::StreamBitrateProperties(data) => data
+                .bitrate_records
+                .sort_by_key(|record| record.flags & 0x7f),
+            HeaderObject::HeaderExtension(data) => {
+                sort_by_stream(&mut data.extension_objects, |object| match object {
+                    ExtensionHeaderObject::ExtendedStreamProperties(data) => {
+                        Some(data.stream_number)
+                    }
+                    _ => None,
+                })
+            }
+            _ => {}
+        }
+    }
+    sort_by_stream(&mut container.header.objects, |object| match object {
+        HeaderObject::StreamProperties(data) => Some(data.stream_number()),
+        _ => None,
+    });
+    refresh(&mut container)?;
+    Ok(container)
+}
+
+/// Changes stream numbers wherever they appear, in the header objects and in
+/// the payloads, following `map` from old to new numbers. Numbers not in
+/// `map` are left alone.
+pub fn renumber(
+    container: &mut Container,
+    map: &[(u16, u16)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some((_, new)) = map.iter().find(|(_, new)| !(1..=0x7f).contains(new)) {
+        return Err(format!("stream number {} is out of range", new).into());
+    }
+    let new = |number: u16| {
+        map.iter()
+            .find(|(old, _)| *old == number)
+            .map_or(number, |&(_, new)| new)
+    };
+    let set = |number: &mut u16| *number = new(*number);
+    // For stream numbers kept in the low bits of flags.
+    let set_flags = |flags: &mut u16| *flags = (*flags & !0x7f) | new(*flags & 0x7f);
+
+    for object in container.header.objects.iter_mut() {
+        match object {
+            HeaderObject::StreamProperties(data) => set_flags(&mut data.flags),
+            HeaderObject::BitrateMutualExclusion(data) => {
+                data.stream_numbers.iter_mut().for_each(set)
+            }
+            HeaderObject::StreamBitrateProperties(data) => {
+                for record in data.bitrate_records.iter_mut() {
+                    set_flags(&mut record.flags);
+                }
+            }
+            HeaderObject::HeaderExtension(data) => {
+                for object in data.extension_objects.iter_mut() {
+                    renumber_extension(object, set, set_flags);
+                }
+            }
+            _ => {}
+        }
+    }
+    for packet in container.data.packets.iter_mut() {
+        for payload in packet.payload.payloads_mut() {
+            let flags = payload.stream_flags_mut();
+            // Checked against the range above.
+            flags.stream_number = new(flags.stream_number.into()) as u8;
+        }
+    }
+    Ok(())
+}
+
+fn renumber_extension(
+    object: &mut ExtensionHeaderObject,
+    set: impl Fn(&mut u16),
+    set_flags: impl Fn(&mut u16),
+) {
+    match object {
+        ExtensionHeaderObject::ExtendedStreamProperties(data) => {
+            set(&mut data.stream_number);
+            if let Some(properties) = &mut data.stream_properties_object {
+                set_flags(&mut properties.flags);
+            }
+        }
+        ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
+            data.stream_numbers.iter_mut().for_each(set)
+        }
+        ExtensionHeaderObject::GroupMutualExclusion(data) => {
+            data.records.iter_mut().flatten().for_each(set)
+        }
+        ExtensionHeaderObject::StreamPrioritization(data) => {
+            for record in data.priority_records.iter_mut() {
+                set(&mut record.stream_number);
+            }
+        }
+        ExtensionHeaderObject::BandwidthSharing(data) => {
+            data.stream_numbers.iter_mut().for_each(set)
+        }
+        ExtensionHeaderObject::Metadata(data) => {
+            for record in data.description_records.iter_mut() {
+                set(&mut record.stream_number);
+            }
+        }
+        ExtensionHeaderObject::MetadataLibrary(data) => {
+            for record in data.description_records.iter_mut() {
+                set(&mut record.stream_number);
+            }
+        }
+        ExtensionHeaderObject::IndexParameters(data) => {
+            for specifier in data.index_specifiers.iter_mut() {
+                set(&mut specifier.stream_number);
+            }
+        }
+        ExtensionHeaderObject::MediaObjectIndexParameters(data) => {
+            for specifier in data.index_specifiers.iter_mut() {
+                set(&mut specifier.stream_number);
+            }
+        }
+        ExtensionHeaderObject::TimecodeIndexParameters(data) => {
+            for specifier in data.index_specifiers.iter_mut() {
+                set(&mut specifier.stream_number);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Drops everything about the streams not in `streams`, and their payloads,
+/// leaving the file to be refreshed.
+fn retain_streams(
+    container: &mut Container,
+    streams: &[u16],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let kept = |number: &u16| streams.contains(number);
+    let header = &mut container.header;
+
+    let unused_codecs = unused_codecs(header, streams);
+    let maximum_bitrate: u64 = header
+        .streams()
+        .iter()
+        .filter(|stream| kept(&stream.number))
+        .filter_map(|stream| stream.bitrate)
+        .map(u64::from)
+        .sum();
+
+    header.objects.retain(|object| match object {
+        HeaderObject::StreamProperties(data) => kept(&data.stream_number()),
+        _ => true,
+    });
+    for object in header.objects.iter_mut() {
+        match object {
+            HeaderObject::CodecList(data) => data.codec_entries.retain(|entry| {
+                !unused_codecs.iter().any(|(codec_type, information)| {
+                    entry.codec_type == *codec_type
+                        && entry.codec_information[..] == information[..]
+                })
+            }),
+            HeaderObject::BitrateMutualExclusion(data) => data.stream_numbers.retain(kept),
+            HeaderObject::StreamBitrateProperties(data) => data
+                .bitrate_records
+                .retain(|record| kept(&(record.flags & 0x7f))),
+            HeaderObject::HeaderExtension(data) => {
+                data.extension_objects.retain(|object| match object {
+                    ExtensionHeaderObject::ExtendedStreamProperties(data) => {
+                        kept(&data.stream_number)
+                    }
+                    _ => true,
+                });
+                for object in data.extension_objects.iter_mut() {
+                    match object {
+                        ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
+                            data.stream_numbers.retain(kept)
+                        }
+                        ExtensionHeaderObject::GroupMutualExclusion(data) => {
+                            for record in data.records.iter_mut() {
+                                record.retain(kept);
+                            }
+                            data.records.retain(|record| !record.is_empty());
+                        }
+                        ExtensionHeaderObject::StreamPrioritization(data) => data
+                            .priority_records
+                            .retain(|record| kept(&record.stream_number)),
+                        ExtensionHeaderObject::BandwidthSharing(data) => {
+                            data.stream_numbers.retain(kept)
+                        }
+                        // Records for stream 0 are about the whole file.
+                        ExtensionHeaderObject::Metadata(data) => {
+                            data.description_records.retain(|record| {
+                                record.stream_number == 0 || kept(&record.stream_number)
+                            })
+                        }
+                        ExtensionHeaderObject::MetadataLibrary(data) => {
+                            data.description_records.retain(|record| {
+                                record.stream_number == 0 || kept(&record.stream_number)
+                            })
+                        }
+                        ExtensionHeaderObject::IndexParameters(data) => data
+                            .index_specifiers
+                            .retain(|specifier| kept(&specifier.stream_number)),
+                        ExtensionHeaderObject::MediaObjectIndexParameters(data) => data
+                            .index_specifiers
+                            .retain(|specifier| kept(&specifier.stream_number)),
+                        ExtensionHeaderObject::TimecodeIndexParameters(data) => data
+                            .index_specifiers
+                            .retain(|specifier| kept(&specifier.stream_number)),
+                        _ => {}
+                    }
+                }
+                // Exclusions between fewer than two choices say nothing.
+                data.extension_objects.retain(|object| match object {
+                    ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
+                        data.stream_numbers.len() > 1
+                    }
+                    ExtensionHeaderObject::GroupMutualExclusion(data) => data.records.len() > 1,
+                    ExtensionHeaderObject::BandwidthSharing(data) => {
+                        !data.stream_numbers.is_empty()
+                    }
+                    ExtensionHeaderObject::IndexParameters(data) => {
+                        !data.index_specifiers.is_empty()
+                    }
+                    ExtensionHeaderObject::MediaObjectIndexParameters(data) => {
+                        !data.index_specifiers.is_empty()
+                    }
+                    ExtensionHeaderObject::TimecodeIndexParameters(data) => {
+                        !data.index_specifiers.is_empty()
+                    }
+                    _ => true,
+                });
+            }
+            _ => {}
+        }
+    }
+    header.objects.retain(|object| match object {
+        HeaderObject::BitrateMutualExclusion(data) => data.stream_numbers.len() > 1,
+        _ => true,
+    });
+
+    let props = header
+        .file_properties_mut()
+        .ok_or("missing file properties object")?;
+    props.maximum_bitrate = maximum_bitrate.try_into().unwrap_or(u32::MAX);
+    let packet_len = props.maximum_data_packet_size;
+
+    let packets = std::mem::take(&mut container.data.packets);
+    let payloads = unpack(packets)
+        .into_iter()
+        .filter(|(_, payload)| kept(&payload.stream_flags().stream_number.into()))
+        .collect();
+    container.data.packets = packetize(payloads, packet_len)?;
+
+    drop_stale_indices(container);
+    Ok(())
+}
+
+/// Drops the index objects that rebuilding the packets leaves out of date,
+/// other than the simple indices `refresh` regenerates.
+pub(crate) fn drop_stale_indices(container: &mut Container) {
+    container.indices.objects.retain(|object| match object {
+        IndexObject::Unknown { guid, .. } => ![
+            INDEX_OBJECT,
+            MEDIA_OBJECT_INDEX_OBJECT,
+            TIMECODE_INDEX_OBJECT,
+        ]
+        .contains(guid),
+        _ => true,
+    });
+}
+
+/// Sorts the items `stream_number` gives a number for by that number,
+/// leaving the other items where they are.
+fn sort_by_stream<T>(items: &mut [T], stream_number: impl Fn(&T) -> Option<u16>) {
+    let slots: Vec<usize> = (0..items.len())
+        .filter(|&i| stream_number(&items[i]).is_some())
+        .collect();
+    for (n, &slot) in slots.iter().enumerate() {
+        let first = slots[n..]
+            .iter()
+            .copied()
+            .min_by_key(|&i| stream_number(&items[i]))
+            .unwrap();
+        items.swap(slot, first);
+    }
+}
+
+/// Codec list entries, by type and codec information, used only by streams
+/// not in `streams`.
+fn unused_codecs(header: &HeaderObjects, streams: &[u16]) -> Vec<(u16, Vec<u8>)> {
+    let mut unused = Vec::new();
+    let mut used = Vec::new();
+    for stream in header.streams() {
+        if let Some(codec) = stream.codec {
+            let codec = (codec.codec_type, codec.codec_information.to_vec());
+            match streams.contains(&stream.number) {
+                true => used.push(codec),
+                false => unused.push(codec),
+            }
+        }
+    }
+    unused.retain(|codec| !used.contains(codec));
+    unused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::file_properties::FilePropertiesData, stream::StreamFormat};
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[test]
+    fn extract_audio() {
+        let container = crate::parse(BASIC_WMV).unwrap();
+        let original = container.header.file_properties().unwrap().clone();
+        let audio_only = Constraints {
+            audio_only: true,
+            ..Default::default()
+        };
+        let container = extract(container, &audio_only).expect("to extract");
+        let mut out = Vec::new();
+        container.write(&mut out).unwrap();
+
+        let container = crate::parse(&out).unwrap();
+        let streams = container.streams();
+        assert_eq!(streams.len(), 1);
+        assert!(matches!(streams[0].format, StreamFormat::Audio { .. }));
+        assert_eq!(streams[0].issues, Vec::new());
+        let codecs = container
+            .header
+            .objects
+            .iter()
+            .find_map(|object| match object {
+                HeaderObject::CodecList(data) => Some(data.codec_entries.len()),
+                _ => None,
+            });
+        assert_eq!(codecs, Some(1));
+        let props = container.header.file_properties().unwrap();
+        assert_eq!(props.maximum_bitrate, streams[0].bitrate.unwrap());
+        assert_eq!(props.play_duration, original.play_duration);
+        assert_eq!(props.send_duration, original.send_duration);
+        assert!(container
+            .data
+            .packets
+            .iter()
+            .flat_map(|packet| packet.payload.payloads())
+            .all(|payload| payload.stream_flags().stream_number == 1));
+        assert!(container.indices.objects.is_empty());
+    }
+
+    #[test]
+    fn keep_all_streams() {
+        let original = crate::parse(BASIC_WMV).unwrap();
+        let original = original.header.file_properties().unwrap();
+        let container = keep_streams(crate::parse(BASIC_WMV).unwrap(), &[1, 2]).unwrap();
+        let props = container.header.file_properties().unwrap();
+        // Only the packing of the payloads into packets changes.
+        assert!(props.data_packets_count <= original.data_packets_count);
+        assert_eq!(
+            *props,
+            FilePropertiesData {
+                file_size: props.file_size,
+                data_packets_count: props.data_packets_count,
+                ..original.clone()
+            }
+        );
+    }
+
+    #[test]
+    fn remux_reorder() {
+        let container = crate::parse(BASIC_WMV).unwrap();
+        let payloads = |container: &Container, number: u8| {
+            container
+                .data
+                .packets
+                .iter()
+                .flat_map(|packet| packet.payload.payloads())
+                .filter(|payload| payload.stream_flags().stream_number == number)
+                .count()
+        };
+        let (audio, video) = (payloads(&container, 1), payloads(&container, 2));
+
+        let container = remux(container, &[2, 1]).expect("to remux");
+        let mut out = Vec::new();
+        container.write(&mut out).unwrap();
+        let container = crate::parse(&out).unwrap();
+
+        let streams = container.streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].number, 1);
+        assert!(matches!(streams[0].format, StreamFormat::Video { .. }));
+        assert_eq!(streams[1].number, 2);
+        assert!(matches!(streams[1].format, StreamFormat::Audio { .. }));
+        assert!(streams.iter().all(|stream| stream.issues.is_empty()));
+        assert!(payloads(&container, 1) >= video);
+        assert!(payloads(&container, 2) >= audio);
+        assert_eq!(container.indices.objects.len(), 1);
+
+        let container = drop_streams(container, &[2]).unwrap();
+        assert_eq!(container.streams().len(), 1);
+        assert_eq!(payloads(&container, 2), 0);
+
+        assert!(remux(container, &[1, 3]).is_err());
+    }
+}
@@ -0,0 +1,459 @@
+//! Choosing which streams to play or extract, following the mutual exclusion,
+//! prioritization and bandwidth sharing objects.
+
+use crate::{
+    guid::*,
+    header::{header_extension::ExtensionHeaderObject, HeaderObject, HeaderObjects},
+    stream::Stream,
+};
+use std::cmp::Reverse;
+use uuid::Uuid;
+
+/// Set in `PriorityRecord::priority_flags` for streams that must be played.
+pub const MANDATORY_FLAG: u16 = 0x0001;
+
+/// What a stream selection must satisfy. The default allows every stream and
+/// any bitrate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Constraints {
+    /// The most bits per second the selected streams may need together.
+    /// Mandatory streams are selected even if they go over it.
+    pub max_bitrate: Option<u64>,
+    /// The language to pick from language exclusions, such as `fr` or
+    /// `fr-ca`.
+    pub language: Option<String>,
+    /// Leave out video and image streams, even mandatory ones.
+    pub audio_only: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    /// The selected stream numbers, in ascending order.
+    pub streams: Vec<u16>,
+    /// The bitrate the selected streams need, counting streams that share
+    /// bandwidth once.
+    pub bitrate: u64,
+}
+
+/// A set of alternatives, of which at most one may be selected.
+struct Exclusion {
+    exclusion_type: Uuid,
+    alternatives: Vec<Vec<u16>>,
+}
+
+/// Streams whose combined bitrate is at most `data_bitrate`.
+struct Sharing {
+    data_bitrate: u64,
+    streams: Vec<u16>,
+}
+
+struct Selector<'s, 'h, 'a> {
+    streams: &'s [Stream<'h, 'a>],
+    sharing: Vec<Sharing>,
+    /// Stream numbers by priority, highest first.
+    order: Vec<u16>,
+}
+
+/// Picks the streams to use under `constraints`.
+///
+/// Streams are considered by priority. Each exclusion gets one alternative:
+/// for language exclusions the one closest to the wanted language, for
+/// bitrate exclusions the highest bitrate that fits, and otherwise the one
+/// with the highest priority that fits. An alternative with a mandatory
+/// stream always wins its exclusion, whether or not it fits. Exclusions
+/// without a mandatory stream are left out if nothing fits, as are other
+/// optional streams.
+pub(crate) fn select_streams(header: &HeaderObjects, constraints: &Constraints) -> Selection {
+    let streams = header.streams();
+    let mut mandatory = Vec::new();
+    let mut order = Vec::new();
+    for object in header.extension_objects() {
+        if let ExtensionHeaderObject::StreamPrioritization(data) = object {
+            for record in data.priority_records.iter() {
+                order.push(record.stream_number);
+                if record.priority_flags & MANDATORY_FLAG != 0 {
+                    mandatory.push(record.stream_number);
+                }
+            }
+        }
+    }
+    for stream in streams.iter() {
+        if !order.contains(&stream.number) {
+            order.push(stream.number);
+        }
+    }
+    let selector = Selector {
+        streams: &streams,
+        sharing: sharing(header),
+        order,
+    };
+    let exclusions = exclusions(header);
+    let allowed = |number: u16| {
+        let stream = streams.iter().find(|stream| stream.number == number);
+        stream.is_some_and(|stream| !(constraints.audio_only && is_visual(stream)))
+    };
+    let fits = |streams: &[u16]| {
+        constraints
+            .max_bitrate
+            .is_none_or(|max_bitrate| selector.cost(streams) <= max_bitrate)
+    };
+
+    let mut selected: Vec<u16> = Vec::new();
+    let mut excluded: Vec<u16> = Vec::new();
+    for &number in selector.order.iter() {
+        if selected.contains(&number) || excluded.contains(&number) || !allowed(number) {
+            continue;
+        }
+        let groups: Vec<&Exclusion> = exclusions
+            .iter()
+            .filter(|exclusion| {
+                exclusion
+                    .alternatives
+                    .iter()
+                    .flatten()
+                    .any(|&n| n == number)
+            })
+            .collect();
+        if groups.is_empty() {
+            let with = [&selected[..], &[number]].concat();
+            if mandatory.contains(&number) || fits(&with) {
+                selected = with;
+            }
+            continue;
+        }
+        for group in groups {
+            if group
+                .alternatives
+                .iter()
+                .any(|alternative| alternative.iter().all(|n| selected.contains(n)))
+            {
+                continue;
+            }
+            let mut candidates: Vec<&Vec<u16>> = group
+                .alternatives
+                .iter()
+                .filter(|alternative| {
+                    !alternative.is_empty()
+                        && alternative
+                            .iter()
+                            .all(|&n| allowed(n) && !excluded.contains(&n))
+                })
+                .collect();
+            candidates.sort_by_key(|alternative| {
+                let language = match group.exclusion_type {
+                    MUTEX_LANGUAGE => selector.language_rank(alternative, constraints),
+                    _ => 0,
+                };
+                let bitrate = match group.exclusion_type {
+                    MUTEX_BITRATE => selector.cost(alternative),
+                    _ => 0,
+                };
+                (language, Reverse(bitrate), selector.priority(alternative))
+            });
+            let with = |alternative: &[u16]| {
+                let mut with = selected.clone();
+                with.extend(alternative.iter().filter(|n| !selected.contains(n)));
+                with
+            };
+            let is_required =
+                |alternative: &&Vec<u16>| alternative.iter().any(|n| mandatory.contains(n));
+            let required = candidates.iter().any(is_required);
+            if required {
+                candidates.retain(is_required);
+            }
+            let choice = candidates
+                .iter()
+                .find(|alternative| fits(&with(alternative)))
+                .or_else(|| match required {
+                    true => candidates
+                        .iter()
+                        .min_by_key(|alternative| selector.cost(&with(alternative))),
+                    false => None,
+                })
+                .copied();
+            for alternative in group.alternatives.iter() {
+                excluded.extend(
+                    alternative
+                        .iter()
+                        .filter(|&n| choice.is_none_or(|choice| !choice.contains(n))),
+                );
+            }
+            if let Some(choice) = choice {
+                selected = with(choice);
+            }
+        }
+    }
+
+    selected.sort_unstable();
+    Selection {
+        bitrate: selector.cost(&selected),
+        streams: selected,
+    }
+}
+
+impl Selector<'_, '_, '_> {
+    fn bitrate(&self, number: u16) -> u64 {
+        let stream = self.streams.iter().find(|stream| stream.number == number);
+        stream.and_then(|stream| stream.bitrate).unwrap_or(0).into()
+    }
+
+    /// The combined bitrate of `streams`. Streams sharing bandwidth need at
+    /// most the bitrate declared for the group.
+    fn cost(&self, streams: &[u16]) -> u64 {
+        let mut cost = 0;
+        let mut counted = Vec::new();
+        for sharing in self.sharing.iter() {
+            let members: Vec<u16> = streams
+                .iter()
+                .copied()
+                .filter(|n| sharing.streams.contains(n) && !counted.contains(n))
+                .collect();
+            let total: u64 = members.iter().map(|&n| self.bitrate(n)).sum();
+            cost += total.min(sharing.data_bitrate);
+            counted.extend(members);
+        }
+        for &number in streams.iter().filter(|n| !counted.contains(n)) {
+            cost += self.bitrate(number);
+        }
+        cost
+    }
+
+    /// The position of the highest priority stream in `streams`.
+    fn priority(&self, streams: &[u16]) -> usize {
+        let position = |n: &u16| self.order.iter().position(|o| o == n);
+        streams
+            .iter()
+            .filter_map(position)
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// 0 for an exact match with the wanted language, 1 for the same primary
+    /// language, such as `en` for `en-us`, and 2 otherwise.
+    fn language_rank(&self, streams: &[u16], constraints: &Constraints) -> u8 {
+        let wanted = match &constraints.language {
+            Some(wanted) => wanted,
+            None => return 0,
+        };
+        let primary = |tag: &str| tag.split('-').next().unwrap_or("").to_ascii_lowercase();
+        let languages = self
+            .streams
+            .iter()
+            .filter(|stream| streams.contains(&stream.number))
+            .filter_map(|stream| stream.language.as_deref());
+        languages
+            .map(|language| match language {
+                _ if language.eq_ignore_ascii_case(wanted) => 0,
+                _ if primary(language) == primary(wanted) => 1,
+                _ => 2,
+            })
+            .min()
+            .unwrap_or(2)
+    }
+}
+
+fn is_visual(stream: &Stream) -> bool {
+    matches!(
+        stream.stream_type(),
+        Some(VIDEO_MEDIA) | Some(JFIF_MEDIA) | Some(DEGRADABLE_JPEG_MEDIA)
+    )
+}
+
+fn exclusions(header: &HeaderObjects) -> Vec<Exclusion> {
+    let singles = |exclusion_type: Uuid, streams: &[u16]| Exclusion {
+        exclusion_type,
+        alternatives: streams.iter().map(|&n| vec![n]).collect(),
+    };
+    let mut exclusions = Vec::new();
+    for object in header.objects.iter() {
+        if let HeaderObject::BitrateMutualExclusion(data) = object {
+            exclusions.push(singles(data.exclusion_type, &data.stream_numbers));
+        }
+    }
+    for object in header.extension_objects() {
+        match object {
+            ExtensionHeaderObject::AdvancedMutualExclusion(data) => {
+                exclusions.push(singles(data.exclusion_type, &data.stream_numbers));
+            }
+            ExtensionHeaderObject::GroupMutualExclusion(data) => exclusions.push(Exclusion {
+                exclusion_type: data.exclusion_type,
+                alternatives: data.records.clone(),
+            }),
+            _ => {}
+        }
+    }
+    exclusions
+}
+
+fn sharing(header: &HeaderObjects) -> Vec<Sharing> {
+    header
+        .extension_objects()
+        .filter_map(|object| match object {
+            ExtensionHeaderObject::BandwidthSharing(data) => Some(Sharing {
+                data_bitrate: data.data_bitrate.into(),
+                streams: data.stream_numbers.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        header::{
+            advanced_mutual_exclusion::AdvancedMutualExclusionData,
+            bandwidth_sharing::BandwidthSharingData,
+            bitrate_mutual_exclusion::BitrateMutualExclusionData,
+            extended_stream_properties::ExtendedStreamPropertiesData,
+            stream_bitrate_properties::{BitrateRecord, StreamBitratePropertiesData},
+            stream_prioritization::{PriorityRecord, StreamPrioritizationData},
+            stream_properties::StreamPropertiesData,
+        },
+        test_support::extended_stream_properties,
+    };
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    fn copy_stream(header: &mut HeaderObjects, from: u16, to: u16) {
+        let from = header
+            .stream_properties()
+            .find(|stream| stream.stream_number() == from)
+            .unwrap();
+        let copy = StreamPropertiesData {
+            flags: to,
+            type_specific_data: from.type_specific_data.clone(),
+            error_correction_data: from.error_correction_data.clone(),
+            ..*from
+        };
+        header.objects.push(HeaderObject::StreamProperties(copy));
+    }
+
+    fn add_extension(header: &mut HeaderObjects, object: ExtensionHeaderObject<'static>) {
+        header.header_extension_mut().extension_objects.push(object);
+    }
+
+    fn set_language(header: &mut HeaderObjects, stream_number: u16, language: &str) {
+        let stream_language_id_index = header.add_language(language).unwrap();
+        let extended = ExtendedStreamPropertiesData {
+            stream_language_id_index,
+            ..extended_stream_properties(stream_number)
+        };
+        add_extension(
+            header,
+            ExtensionHeaderObject::ExtendedStreamProperties(extended),
+        );
+    }
+
+    /// Audio streams 1 and 4 and video streams 2 and 3, of which only one of
+    /// each may be picked.
+    fn multi_bitrate_header() -> HeaderObjects<'static> {
+        let mut header = crate::parse(BASIC_WMV).unwrap().header.into_owned();
+        copy_stream(&mut header, 2, 3);
+        copy_stream(&mut header, 1, 4);
+        header
+            .objects
+            .retain(|object| !matches!(object, HeaderObject::StreamBitrateProperties(_)));
+        let bitrates = [(1, 32_000), (2, 500_000), (3, 200_000), (4, 48_000)];
+        let bitrate_records = bitrates
+            .iter()
+            .map(|&(flags, average_bitrate)| BitrateRecord {
+                flags,
+                average_bitrate,
+            })
+            .collect();
+        header.objects.push(HeaderObject::StreamBitrateProperties(
+            StreamBitratePropertiesData { bitrate_records },
+        ));
+        header.objects.push(HeaderObject::BitrateMutualExclusion(
+            BitrateMutualExclusionData {
+                exclusion_type: MUTEX_BITRATE,
+                stream_numbers: vec![2, 3],
+            },
+        ));
+        set_language(&mut header, 1, "en-us");
+        set_language(&mut header, 4, "fr-fr");
+        header
+    }
+
+    fn select(header: &HeaderObjects, constraints: Constraints) -> Vec<u16> {
+        header.select_streams(&constraints).streams
+    }
+
+    #[test]
+    fn select_bitrate_and_language() {
+        let mut header = multi_bitrate_header();
+        let selection = header.select_streams(&Constraints::default());
+        assert_eq!(selection.streams, vec![1, 2, 4]);
+        assert_eq!(selection.bitrate, 580_000);
+
+        let low = Constraints {
+            max_bitrate: Some(300_000),
+            ..Default::default()
+        };
+        assert_eq!(select(&header, low.clone()), vec![1, 3, 4]);
+        let audio_only = Constraints {
+            audio_only: true,
+            ..Default::default()
+        };
+        assert_eq!(select(&header, audio_only), vec![1, 4]);
+
+        add_extension(
+            &mut header,
+            ExtensionHeaderObject::AdvancedMutualExclusion(AdvancedMutualExclusionData {
+                exclusion_type: MUTEX_LANGUAGE,
+                stream_numbers: vec![1, 4],
+            }),
+        );
+        assert_eq!(select(&header, Constraints::default()), vec![1, 2]);
+        let french = Constraints {
+            language: Some("fr".to_string()),
+            ..low
+        };
+        assert_eq!(select(&header, french), vec![3, 4]);
+    }
+
+    #[test]
+    fn select_mandatory() {
+        // Stream 2 comes first, but only stream 3 is mandatory.
+        let mut header = multi_bitrate_header();
+        let priorities = [(2, 0), (3, MANDATORY_FLAG), (1, 0), (4, 0)];
+        let priority_records = priorities
+            .iter()
+            .map(|&(stream_number, priority_flags)| PriorityRecord {
+                stream_number,
+                priority_flags,
+            })
+            .collect();
+        add_extension(
+            &mut header,
+            ExtensionHeaderObject::StreamPrioritization(StreamPrioritizationData {
+                priority_records,
+            }),
+        );
+        assert_eq!(select(&header, Constraints::default()), vec![1, 3, 4]);
+        let tiny = Constraints {
+            max_bitrate: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(select(&header, tiny), vec![3]);
+    }
+
+    #[test]
+    fn select_shared_bandwidth() {
+        let mut header = multi_bitrate_header();
+        add_extension(
+            &mut header,
+            ExtensionHeaderObject::BandwidthSharing(BandwidthSharingData {
+                sharing_type: BANDWIDTH_SHARING_EXCLUSIVE,
+                data_bitrate: 48_000,
+                buffer_size: 0,
+                stream_numbers: vec![1, 4],
+            }),
+        );
+        let selection = header.select_streams(&Constraints::default());
+        assert_eq!(selection.streams, vec![1, 2, 4]);
+        assert_eq!(selection.bitrate, 548_000);
+    }
+}
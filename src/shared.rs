@@ -0,0 +1,336 @@
+//! Parsing into packets that share the source buffer through `bytes::Bytes`
+//! instead of borrowing it, so they can be stored and forwarded without a
+//! lifetime or a copy. The header and indices are small and are copied.
+
+use crate::{
+    data::{
+        packets::{
+            DataPacket, ErrorCorrectionData, FieldType, Payload, PayloadData, PayloadParsingData,
+            StreamFlags,
+        },
+        DamagedRange, DataObject, Truncation,
+    },
+    error::AsfError,
+    guid::*,
+    header::HeaderObjects,
+    index::IndexObjects,
+    options::ParseOptions,
+};
+use bytes::Bytes;
+use std::{borrow::Cow, convert::TryInto, io::Write};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq)]
+pub struct SharedContainer {
+    pub header: HeaderObjects<'static>,
+    pub data: SharedDataObject,
+    pub indices: IndexObjects<'static>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SharedDataObject {
+    pub file_id: Uuid,
+    pub total_data_packets: u64,
+    pub reserved: u16,
+    pub packets: Vec<SharedPacket>,
+    pub damaged: Vec<DamagedRange>,
+    pub truncation: Option<Truncation>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedPacket {
+    pub error_correction_data: Option<ErrorCorrectionData>,
+    pub payload_parsing_data: PayloadParsingData,
+    pub payload: SharedPayloadData,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SharedPayloadData {
+    SinglePayload(SharedPayload),
+    MultiplePayloads {
+        payload_len_type: FieldType,
+        payloads: Vec<SharedPayload>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SharedPayload {
+    NormalPayload {
+        stream_flags: StreamFlags,
+        media_object_number: u32,
+        offset_into_media_object: u32,
+        replicated_data: Bytes,
+        payload_data: Bytes,
+    },
+    CompressedPayload {
+        stream_flags: StreamFlags,
+        media_object_number: u32,
+        presentation_time: u32,
+        presentation_time_delta: u8,
+        sub_payload_data: Vec<Bytes>,
+    },
+}
+
+pub fn parse(source: &Bytes) -> Result<SharedContainer, AsfError> {
+    parse_with_options(source, &ParseOptions::default())
+}
+
+pub fn parse_with_options(
+    source: &Bytes,
+    options: &ParseOptions,
+) -> Result<SharedContainer, AsfError> {
+    let container = crate::parse_with_options(source, options)?;
+    Ok(SharedContainer {
+        header: container.header.into_owned(),
+        data: SharedDataObject::new(container.data, source),
+        indices: container.indices.into_owned(),
+    })
+}
+
+/// Slices `data` out of `source` if it was borrowed from there, and takes
+/// ownership of it otherwise.
+fn share(data: Cow<[u8]>, source: &Bytes) -> Bytes {
+    match data {
+        Cow::Borrowed([]) => Bytes::new(),
+        Cow::Borrowed(data) => source.slice_ref(data),
+        Cow::Owned(data) => Bytes::from(data),
+    }
+}
+
+impl SharedContainer {
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        self.header.write(w)?;
+        self.data.write(w)?;
+        self.indices.write(w)?;
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += self.header.size_of();
+        len += self.data.size_of();
+        len += self.indices.size_of();
+        len
+    }
+}
+
+impl SharedDataObject {
+    /// Converts a data object parsed from `source`.
+    pub fn new(data: DataObject, source: &Bytes) -> Self {
+        SharedDataObject {
+            file_id: data.file_id,
+            total_data_packets: data.total_data_packets,
+            reserved: data.reserved,
+            packets: data
+                .packets
+                .into_iter()
+                .map(|packet| SharedPacket::new(packet, source))
+                .collect(),
+            damaged: data.damaged,
+            truncation: data.truncation,
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        let size: u64 = self.size_of().try_into()?;
+        w.write_all(&DATA_OBJECT.as_bytes_ms())?;
+        w.write_all(&size.to_le_bytes())?;
+        w.write_all(&self.file_id.as_bytes_ms())?;
+        w.write_all(&self.total_data_packets.to_le_bytes())?;
+        w.write_all(&self.reserved.to_le_bytes())?;
+        for packet in self.packets.iter() {
+            packet.write(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn size_of(&self) -> usize {
+        let mut len = 0;
+        len += 16;
+        len += 8;
+        len += 16;
+        len += 8;
+        len += 2;
+        for packet in self.packets.iter() {
+            len += packet.size_of();
+        }
+        len
+    }
+}
+
+impl SharedPacket {
+    /// Converts a packet parsed from `source`.
+    pub fn new(packet: DataPacket, source: &Bytes) -> Self {
+        SharedPacket {
+            error_correction_data: packet.error_correction_data,
+            payload_parsing_data: packet.payload_parsing_data,
+            payload: SharedPayloadData::new(packet.payload, source),
+        }
+    }
+
+    /// Borrows the packet as a `DataPacket`.
+    pub fn as_packet(&self) -> DataPacket<'_> {
+        DataPacket {
+            error_correction_data: self.error_correction_data,
+            payload_parsing_data: self.payload_parsing_data,
+            payload: self.payload.as_payload_data(),
+        }
+    }
+
+    pub fn write<T: Write>(&self, w: &mut T) -> Result<(), Box<dyn std::error::Error>> {
+        self.as_packet().write(w)
+    }
+
+    pub fn size_of(&self) -> usize {
+        self.as_packet().size_of()
+    }
+}
+
+impl SharedPayloadData {
+    pub fn new(payload: PayloadData, source: &Bytes) -> Self {
+        match payload {
+            PayloadData::SinglePayload(payload) => {
+                SharedPayloadData::SinglePayload(SharedPayload::new(payload, source))
+            }
+            PayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads,
+            } => SharedPayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads: payloads
+                    .into_iter()
+                    .map(|payload| SharedPayload::new(payload, source))
+                    .collect(),
+            },
+        }
+    }
+
+    pub fn as_payload_data(&self) -> PayloadData<'_> {
+        match self {
+            SharedPayloadData::SinglePayload(payload) => {
+                PayloadData::SinglePayload(payload.as_payload())
+            }
+            SharedPayloadData::MultiplePayloads {
+                payload_len_type,
+                payloads,
+            } => PayloadData::MultiplePayloads {
+                payload_len_type: *payload_len_type,
+                payloads: payloads.iter().map(SharedPayload::as_payload).collect(),
+            },
+        }
+    }
+
+    pub fn payloads(&self) -> &[SharedPayload] {
+        match self {
+            SharedPayloadData::SinglePayload(payload) => std::slice::from_ref(payload),
+            SharedPayloadData::MultiplePayloads { payloads, .. } => payloads,
+        }
+    }
+}
+
+impl SharedPayload {
+    pub fn new(payload: Payload, source: &Bytes) -> Self {
+        match payload {
+            Payload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data,
+                payload_data,
+            } => SharedPayload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data: share(replicated_data, source),
+                payload_data: share(payload_data, source),
+            },
+            Payload::CompressedPayload {
+                stream_flags,
+                media_object_number,
+                presentation_time,
+                presentation_time_delta,
+                sub_payload_data,
+            } => SharedPayload::CompressedPayload {
+                stream_flags,
+                media_object_number,
+                presentation_time,
+                presentation_time_delta,
+                sub_payload_data: sub_payload_data
+                    .into_iter()
+                    .map(|data| share(data, source))
+                    .collect(),
+            },
+        }
+    }
+
+    pub fn as_payload(&self) -> Payload<'_> {
+        match self {
+            SharedPayload::NormalPayload {
+                stream_flags,
+                media_object_number,
+                offset_into_media_object,
+                replicated_data,
+                payload_data,
+            } => Payload::NormalPayload {
+                stream_flags: *stream_flags,
+                media_object_number: *media_object_number,
+                offset_into_media_object: *offset_into_media_object,
+                replicated_data: Cow::Borrowed(replicated_data),
+                payload_data: Cow::Borrowed(payload_data),
+            },
+            SharedPayload::CompressedPayload {
+                stream_flags,
+                media_object_number,
+                presentation_time,
+                presentation_time_delta,
+                sub_payload_data,
+            } => Payload::CompressedPayload {
+                stream_flags: *stream_flags,
+                media_object_number: *media_object_number,
+                presentation_time: *presentation_time,
+                presentation_time_delta: *presentation_time_delta,
+                sub_payload_data: sub_payload_data
+                    .iter()
+                    .map(|data| Cow::Borrowed(&data[..]))
+                    .collect(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[test]
+    fn shared_packets() {
+        let source = Bytes::from_static(BASIC_WMV);
+        let shared = parse(&source).expect("to parse successfully");
+        let container = crate::parse(BASIC_WMV).expect("to parse successfully");
+        assert_eq!(shared.header, container.header);
+        assert_eq!(shared.data.packets.len(), container.data.packets.len());
+        for (shared, packet) in shared
+            .data
+            .packets
+            .iter()
+            .zip(container.data.packets.iter())
+        {
+            assert_eq!(&shared.as_packet(), packet);
+        }
+
+        // Payloads point into the source rather than at copies.
+        let payload = match &shared.data.packets[0].payload.payloads()[0] {
+            SharedPayload::NormalPayload { payload_data, .. } => payload_data,
+            SharedPayload::CompressedPayload { .. } => panic!("expected a normal payload"),
+        };
+        let range = source.as_ptr_range();
+        assert!(range.contains(&payload.as_ptr()));
+
+        let mut out = Vec::new();
+        shared.write(&mut out).expect("write to succeed");
+        assert_eq!(shared.size_of(), BASIC_WMV.len());
+        assert_eq!(out.len(), BASIC_WMV.len());
+    }
+}
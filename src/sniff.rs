@@ -0,0 +1,112 @@
+//! Recognizing ASF files and telling the Windows Media flavors apart.
+
+use crate::{guid::*, header::HeaderObjects, stream::StreamFormat};
+
+/// Whether `data` starts with the header object GUID. At least 16 bytes are
+/// needed.
+pub fn sniff(data: &[u8]) -> bool {
+    data.starts_with(&HEADER_OBJECT.as_bytes_ms())
+}
+
+/// The kind of content in a file, for choosing its MIME type and extension.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MediaType {
+    /// Windows Media Audio streams only.
+    Wma,
+    /// Windows Media Video, possibly with Windows Media Audio.
+    Wmv,
+    /// Anything else.
+    Asf,
+}
+
+/// WAVEFORMATEX format tags of the Windows Media Audio codecs.
+const WMA_FORMAT_TAGS: [u16; 5] = [0x000a, 0x0160, 0x0161, 0x0162, 0x0163];
+
+/// BITMAPINFOHEADER compression FourCCs of the Windows Media Video codecs,
+/// including the Microsoft MPEG-4 codecs that came before them.
+const WMV_FOURCCS: [&[u8; 4]; 14] = [
+    b"WMV1", b"WMV2", b"WMV3", b"WMVA", b"WMVP", b"WVC1", b"WVP2", b"MSS1", b"MSS2", b"MP4S",
+    b"M4S2", b"MP42", b"MP43", b"MPG4",
+];
+
+impl MediaType {
+    /// Classifies a file by the types and codecs of its streams.
+    pub fn classify(header: &HeaderObjects) -> Self {
+        let mut audio = false;
+        let mut video = false;
+        for stream in header.streams() {
+            match stream.format {
+                StreamFormat::Audio { format_tag, .. } if WMA_FORMAT_TAGS.contains(&format_tag) => {
+                    audio = true
+                }
+                StreamFormat::Video { compression, .. } if WMV_FOURCCS.contains(&&compression) => {
+                    video = true
+                }
+                StreamFormat::Command => {}
+                _ => return MediaType::Asf,
+            }
+        }
+        match (audio, video) {
+            (_, true) => MediaType::Wmv,
+            (true, false) => MediaType::Wma,
+            (false, false) => MediaType::Asf,
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            MediaType::Wma => "audio/x-ms-wma",
+            MediaType::Wmv => "video/x-ms-wmv",
+            MediaType::Asf => "video/x-ms-asf",
+        }
+    }
+
+    /// The usual file extension, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MediaType::Wma => "wma",
+            MediaType::Wmv => "wmv",
+            MediaType::Asf => "asf",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::HeaderObject;
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+    const BASIC_DRMV2_WMA: &[u8] = include_bytes!("../samples/basic-drmv2.wma");
+
+    #[test]
+    fn sniff_header() {
+        assert!(sniff(BASIC_WMV));
+        assert!(sniff(&BASIC_WMV[..16]));
+        assert!(!sniff(&BASIC_WMV[..15]));
+        assert!(!sniff(&BASIC_WMV[1..]));
+    }
+
+    #[test]
+    fn classify_samples() {
+        let wmv = crate::parse(BASIC_WMV).unwrap().media_type();
+        assert_eq!(wmv, MediaType::Wmv);
+        assert_eq!(wmv.mime_type(), "video/x-ms-wmv");
+        assert_eq!(wmv.extension(), "wmv");
+
+        let wma = crate::probe(BASIC_DRMV2_WMA).unwrap().media_type();
+        assert_eq!(wma, MediaType::Wma);
+        assert_eq!(wma.mime_type(), "audio/x-ms-wma");
+
+        let mut header = crate::parse(BASIC_WMV).unwrap().header;
+        header.objects.retain(|object| match object {
+            HeaderObject::StreamProperties(data) => data.stream_type != VIDEO_MEDIA,
+            _ => true,
+        });
+        assert_eq!(header.media_type(), MediaType::Wma);
+        header
+            .objects
+            .retain(|object| !matches!(object, HeaderObject::StreamProperties(_)));
+        assert_eq!(header.media_type(), MediaType::Asf);
+    }
+}
@@ -0,0 +1,360 @@
+//! A single view of each stream, joining everything the header objects say
+//! about it by stream number.
+
+use crate::{
+    guid::*,
+    header::{
+        codec_list::CodecEntry, extended_stream_properties::ExtendedStreamPropertiesData,
+        header_extension::ExtensionHeaderObject, metadata, metadata_library,
+        stream_properties::StreamPropertiesData, HeaderObject, HeaderObjects,
+    },
+};
+use std::convert::TryInto;
+use uuid::Uuid;
+
+/// Set in `StreamPropertiesData::flags` when the stream's content is
+/// encrypted.
+pub const ENCRYPTED_CONTENT_FLAG: u16 = 0x8000;
+
+/// Everything the header says about one stream.
+#[derive(Debug, PartialEq)]
+pub struct Stream<'h, 'a> {
+    pub number: u16,
+    /// The stream properties object, either at the top level or embedded in
+    /// the extended stream properties.
+    pub properties: Option<&'h StreamPropertiesData<'a>>,
+    pub extended: Option<&'h ExtendedStreamPropertiesData<'a>>,
+    pub format: StreamFormat,
+    /// The codec list entry matching the format, if there is one.
+    pub codec: Option<&'h CodecEntry<'a>>,
+    /// Average bitrate in bits per second, from the stream bitrate
+    /// properties, or else from the extended stream properties.
+    pub bitrate: Option<u32>,
+    pub language: Option<String>,
+    /// Stream names, each with its language if one is given.
+    pub names: Vec<(Option<String>, String)>,
+    pub encrypted: bool,
+    /// Presentation time offset in 100-nanosecond units.
+    pub time_offset: u64,
+    /// Start and end of the stream in milliseconds, when set.
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    /// Average frame duration in 100-nanosecond units, when set.
+    pub average_time_per_frame: Option<u64>,
+    pub metadata: Vec<&'h metadata::DescriptionRecord<'a>>,
+    pub metadata_library: Vec<&'h metadata_library::DescriptionRecord<'a>>,
+    /// Ways the header objects disagree about the stream.
+    pub issues: Vec<StreamIssue>,
+}
+
+/// A stream's media format, from its type-specific data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamFormat {
+    /// A WAVEFORMATEX.
+    Audio {
+        format_tag: u16,
+        channels: u16,
+        samples_per_second: u32,
+        average_bytes_per_second: u32,
+        block_align: u16,
+        bits_per_sample: u16,
+    },
+    /// The image size and the start of the BITMAPINFOHEADER.
+    Video {
+        width: u32,
+        height: u32,
+        bit_count: u16,
+        compression: [u8; 4],
+    },
+    Command,
+    /// Any other stream type, or type-specific data too short to read.
+    Other(Uuid),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamIssue {
+    /// More than one stream properties object has this stream number.
+    DuplicateProperties,
+    /// There are extended stream properties but no stream properties.
+    MissingProperties,
+    /// The type-specific data is too short for the stream type.
+    MalformedFormat,
+    /// A language index is outside the language list.
+    BadLanguageIndex(u16),
+    /// The codec list has entries for this kind of stream, but none for its
+    /// codec.
+    UnlistedCodec,
+}
+
+/// Codec list entry types.
+const VIDEO_CODEC: u16 = 1;
+const AUDIO_CODEC: u16 = 2;
+
+impl StreamFormat {
+    pub fn parse(stream_type: Uuid, data: &[u8]) -> Self {
+        let u16_at = |at: usize| u16::from_le_bytes(data[at..at + 2].try_into().unwrap());
+        let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        match stream_type {
+            AUDIO_MEDIA if data.len() >= 16 => StreamFormat::Audio {
+                format_tag: u16_at(0),
+                channels: u16_at(2),
+                samples_per_second: u32_at(4),
+                average_bytes_per_second: u32_at(8),
+                block_align: u16_at(12),
+                bits_per_sample: u16_at(14),
+            },
+            // The image size, flags and format data size come before the
+            // BITMAPINFOHEADER.
+            VIDEO_MEDIA if data.len() >= 31 => StreamFormat::Video {
+                width: u32_at(0),
+                height: u32_at(4),
+                bit_count: u16_at(25),
+                compression: data[27..31].try_into().unwrap(),
+            },
+            COMMAND_MEDIA => StreamFormat::Command,
+            stream_type => StreamFormat::Other(stream_type),
+        }
+    }
+
+    /// The codec list entry type and information identifying this codec.
+    fn codec(&self) -> Option<(u16, Vec<u8>)> {
+        match self {
+            StreamFormat::Audio { format_tag, .. } => {
+                Some((AUDIO_CODEC, format_tag.to_le_bytes().to_vec()))
+            }
+            StreamFormat::Video { compression, .. } => Some((VIDEO_CODEC, compression.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+impl<'h, 'a> Stream<'h, 'a> {
+    fn new(number: u16) -> Self {
+        Stream {
+            number,
+            properties: None,
+            extended: None,
+            format: StreamFormat::Other(Uuid::nil()),
+            codec: None,
+            bitrate: None,
+            language: None,
+            names: Vec::new(),
+            encrypted: false,
+            time_offset: 0,
+            start_time: None,
+            end_time: None,
+            average_time_per_frame: None,
+            metadata: Vec::new(),
+            metadata_library: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    pub fn stream_type(&self) -> Option<Uuid> {
+        self.properties.map(|properties| properties.stream_type)
+    }
+}
+
+/// Gathers the streams in `header`, in order of first appearance.
+pub(crate) fn streams<'h, 'a>(header: &'h HeaderObjects<'a>) -> Vec<Stream<'h, 'a>> {
+    let mut streams = Vec::new();
+    for properties in header.stream_properties() {
+        add_properties(&mut streams, properties);
+    }
+    for object in header.extension_objects() {
+        if let ExtensionHeaderObject::ExtendedStreamProperties(extended) = object {
+            entry(&mut streams, extended.stream_number).extended = Some(extended);
+            if let Some(properties) = &extended.stream_properties_object {
+                add_properties(&mut streams, properties);
+            }
+        }
+    }
+
+    let codecs: Vec<&CodecEntry> = header
+        .objects
+        .iter()
+        .filter_map(|object| match object {
+            HeaderObject::CodecList(data) => Some(data.codec_entries.iter()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    for stream in streams.iter_mut() {
+        if let Some(extended) = stream.extended {
+            let issues = &mut stream.issues;
+            stream.language = language_at(header, extended.stream_language_id_index, issues);
+            stream.names = extended
+                .stream_names
+                .iter()
+                .map(|name| {
+                    let language = language_at(header, name.language_id_index, issues);
+                    (language, name.stream_name.to_str())
+                })
+                .collect();
+            stream.bitrate = Some(extended.data_bitrate);
+            stream.start_time = Some(extended.start_time).filter(|&time| time != 0);
+            stream.end_time = Some(extended.end_time).filter(|&time| time != 0);
+            stream.average_time_per_frame =
+                Some(extended.average_time_per_frame).filter(|&time| time != 0);
+        }
+
+        match stream.properties {
+            Some(properties) => {
+                stream.format =
+                    StreamFormat::parse(properties.stream_type, &properties.type_specific_data);
+                if let StreamFormat::Other(AUDIO_MEDIA) | StreamFormat::Other(VIDEO_MEDIA) =
+                    stream.format
+                {
+                    stream.issues.push(StreamIssue::MalformedFormat);
+                }
+                stream.encrypted = properties.flags & ENCRYPTED_CONTENT_FLAG != 0;
+                stream.time_offset = properties.time_offset;
+            }
+            None => stream.issues.push(StreamIssue::MissingProperties),
+        }
+
+        if let Some((codec_type, information)) = stream.format.codec() {
+            let mut entries = codecs.iter().filter(|entry| entry.codec_type == codec_type);
+            let listed = entries.clone().next().is_some();
+            stream.codec = entries
+                .find(|entry| entry.codec_information[..] == information[..])
+                .copied();
+            if listed && stream.codec.is_none() {
+                stream.issues.push(StreamIssue::UnlistedCodec);
+            }
+        }
+    }
+
+    for object in header.objects.iter() {
+        if let HeaderObject::StreamBitrateProperties(data) = object {
+            for record in data.bitrate_records.iter() {
+                if let Some(stream) = find(&mut streams, record.flags & 0x7f) {
+                    stream.bitrate = Some(record.average_bitrate);
+                }
+            }
+        }
+    }
+    for object in header.extension_objects() {
+        match object {
+            ExtensionHeaderObject::Metadata(data) => {
+                for record in data.description_records.iter() {
+                    if let Some(stream) = find(&mut streams, record.stream_number) {
+                        stream.metadata.push(record);
+                    }
+                }
+            }
+            ExtensionHeaderObject::MetadataLibrary(data) => {
+                for record in data.description_records.iter() {
+                    if let Some(stream) = find(&mut streams, record.stream_number) {
+                        stream.metadata_library.push(record);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    streams
+}
+
+fn find<'s, 'h, 'a>(
+    streams: &'s mut [Stream<'h, 'a>],
+    number: u16,
+) -> Option<&'s mut Stream<'h, 'a>> {
+    streams.iter_mut().find(|stream| stream.number == number)
+}
+
+fn entry<'s, 'h, 'a>(streams: &'s mut Vec<Stream<'h, 'a>>, number: u16) -> &'s mut Stream<'h, 'a> {
+    match streams.iter().position(|stream| stream.number == number) {
+        Some(index) => &mut streams[index],
+        None => {
+            streams.push(Stream::new(number));
+            streams.last_mut().unwrap()
+        }
+    }
+}
+
+fn add_properties<'h, 'a>(
+    streams: &mut Vec<Stream<'h, 'a>>,
+    properties: &'h StreamPropertiesData<'a>,
+) {
+    let stream = entry(streams, properties.stream_number());
+    if stream.properties.is_some() {
+        stream.issues.push(StreamIssue::DuplicateProperties);
+    }
+    stream.properties = Some(properties);
+}
+
+fn language_at(
+    header: &HeaderObjects,
+    index: u16,
+    issues: &mut Vec<StreamIssue>,
+) -> Option<String> {
+    let language = header.language(index);
+    if language.is_none() {
+        issues.push(StreamIssue::BadLanguageIndex(index));
+    }
+    language
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    #[test]
+    fn basic_wmv_streams() {
+        let container = crate::parse(BASIC_WMV).unwrap();
+        let streams = container.streams();
+        assert_eq!(streams.len(), 2);
+
+        let audio = &streams[0];
+        assert_eq!(audio.number, 1);
+        assert!(matches!(
+            audio.format,
+            StreamFormat::Audio {
+                format_tag: 0x0161,
+                channels: 1,
+                samples_per_second: 16000,
+                ..
+            }
+        ));
+        assert!(audio.codec.is_some());
+        assert!(audio.bitrate.is_some());
+        assert_eq!(audio.issues, Vec::new());
+
+        let video = &streams[1];
+        assert_eq!(video.number, 2);
+        assert!(matches!(
+            video.format,
+            StreamFormat::Video {
+                width: 320,
+                height: 240,
+                compression: [b'M', b'P', b'4', b'S'],
+                ..
+            }
+        ));
+        assert_eq!(video.issues, Vec::new());
+    }
+
+    #[test]
+    fn stream_issues() {
+        let mut header = crate::parse(BASIC_WMV).unwrap().header;
+        let copy = header.stream_properties().next().unwrap();
+        let copy = StreamPropertiesData {
+            type_specific_data: copy.type_specific_data[..4].to_vec().into(),
+            error_correction_data: copy.error_correction_data.clone(),
+            ..*copy
+        };
+        header.objects.push(HeaderObject::StreamProperties(copy));
+        let streams = header.streams();
+        assert_eq!(
+            streams[0].issues,
+            vec![
+                StreamIssue::DuplicateProperties,
+                StreamIssue::MalformedFormat
+            ]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::{
+    data::packets::FieldType, guid::AsBytesMs,
+    header::extended_stream_properties::ExtendedStreamPropertiesData,
+};
+use uuid::Uuid;
+
+const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+/// The offset of the first occurrence of `id` in `buf`.
+pub(crate) fn find_guid(buf: &[u8], id: Uuid) -> usize {
+    buf.windows(16)
+        .position(|w| w == id.as_bytes_ms())
+        .expect("guid to be present")
+}
+
+/// Extended stream properties for `stream_number`, with every other field
+/// zero or empty.
+pub(crate) fn extended_stream_properties(
+    stream_number: u16,
+) -> ExtendedStreamPropertiesData<'static> {
+    ExtendedStreamPropertiesData {
+        start_time: 0,
+        end_time: 0,
+        data_bitrate: 0,
+        buffer_size: 0,
+        initial_buffer_fullness: 0,
+        alternate_data_bitrate: 0,
+        alternate_buffer_size: 0,
+        alternate_initial_buffer_fullness: 0,
+        maximum_object_size: 0,
+        flags: 0,
+        stream_number,
+        stream_language_id_index: 0,
+        average_time_per_frame: 0,
+        stream_names: Vec::new(),
+        payload_extension_systems: Vec::new(),
+        stream_properties_object: None,
+    }
+}
+
+/// `basic.wmv` with every packet giving its own length, without padding, and
+/// the sizes of its packets.
+pub(crate) fn variable_size_wmv() -> (Vec<u8>, Vec<usize>) {
+    let mut container = crate::parse(BASIC_WMV).unwrap();
+    for packet in container.data.packets.iter_mut() {
+        let ppd = &mut packet.payload_parsing_data;
+        ppd.length_type_flags.packet_len_type = FieldType::Word;
+        ppd.padding_len = 0;
+        packet.payload_parsing_data.packet_length = packet.size_of() as u32;
+    }
+    let props = container.header.file_properties_mut().unwrap();
+    props.minimum_data_packet_size = 0;
+    props.maximum_data_packet_size = 0;
+    let sizes = container.data.packets.iter().map(|p| p.size_of()).collect();
+    let mut buf = Vec::new();
+    container.write(&mut buf).unwrap();
+    (buf, sizes)
+}
@@ -0,0 +1,251 @@
+//! Cutting a file down to a time range without re-encoding it.
+
+use crate::{
+    data::{
+        packetize::{packetize, unpack},
+        packets::{DataPacket, Payload},
+    },
+    finalize::refresh,
+    guid::*,
+    header::HeaderObject,
+    remux::drop_stale_indices,
+    Container,
+};
+use std::convert::TryInto;
+
+/// Makes a file of the part of `container` from `start` up to `end`, in
+/// milliseconds from the start of the presentation, not counting the
+/// preroll.
+///
+/// The file starts at the last video key frame at or before `start`, so it
+/// can be decoded from its first packet; the earliest such key frame across
+/// the video streams is used. Times are rebased to that clean point, and the
+/// markers and script commands in the range kept. Payloads without a
+/// presentation time are kept by their send time. The packets are rebuilt at
+/// the same packet size, and the durations cut down to the range.
+pub fn trim<'a>(
+    mut container: Container<'a>,
+    start: u32,
+    end: u32,
+) -> Result<Container<'a>, Box<dyn std::error::Error>> {
+    if end <= start {
+        return Err("the time range is empty".into());
+    }
+    let props = container
+        .header
+        .file_properties()
+        .ok_or("missing file properties object")?;
+    let preroll: u32 = props.preroll.try_into()?;
+    let packet_len = props.maximum_data_packet_size;
+    let (play_duration, send_duration) = (props.play_duration, props.send_duration);
+    let video: Vec<u8> = container
+        .header
+        .stream_properties()
+        .filter(|stream| stream.stream_type == VIDEO_MEDIA)
+        .map(|stream| stream.stream_number() as u8)
+        .collect();
+
+    let payloads = unpack(std::mem::take(&mut container.data.packets));
+    // The range in presentation times, which count the preroll.
+    let cut = clean_point(&payloads, &video, start.saturating_add(preroll)).max(preroll);
+    let end = end.saturating_add(preroll);
+    let shift = cut - preroll;
+    let payloads = payloads
+        .into_iter()
+        .filter(|(send_time, payload)| match payload.presentation_time() {
+            Some(time) => (cut..end).contains(&time),
+            None => (shift..end - preroll).contains(send_time),
+        })
+        .map(|(send_time, mut payload)| {
+            if let Some(time) = payload.presentation_time() {
+                payload.set_presentation_time(time - shift);
+            }
+            (send_time.saturating_sub(shift), payload)
+        })
+        .collect();
+    container.data.packets = packetize(payloads, packet_len)?;
+
+    let packets = &container.data.packets;
+    for object in container.header.objects.iter_mut() {
+        match object {
+            HeaderObject::Marker(data) => {
+                let range = u64::from(cut) * 10_000..u64::from(end) * 10_000;
+                data.markers
+                    .retain(|marker| range.contains(&marker.presentation_time));
+                for marker in data.markers.iter_mut() {
+                    marker.presentation_time -= u64::from(shift) * 10_000;
+                    marker.send_time = marker.send_time.saturating_sub(shift);
+                    let time = (marker.presentation_time / 10_000) as u32;
+                    marker.offset = (packet_at(packets, time) * packet_len as usize) as u64;
+                }
+            }
+            HeaderObject::ScriptCommand(data) => {
+                data.commands
+                    .retain(|command| (cut..end).contains(&command.presentation_time));
+                for command in data.commands.iter_mut() {
+                    command.presentation_time -= shift;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The durations count the preroll, and the send duration doesn't.
+    let props = container.header.file_properties_mut().unwrap();
+    props.play_duration = play_duration
+        .min(u64::from(end) * 10_000)
+        .saturating_sub(u64::from(shift) * 10_000);
+    props.send_duration = send_duration
+        .min(u64::from(end - preroll) * 10_000)
+        .saturating_sub(u64::from(shift) * 10_000);
+
+    drop_stale_indices(&mut container);
+    refresh(&mut container)?;
+    Ok(container)
+}
+
+/// The presentation time of the earliest of the last key frames at or before
+/// `time` in each of the `video` streams, or `time` if there are none.
+fn clean_point(payloads: &[(u32, Payload)], video: &[u8], time: u32) -> u32 {
+    video
+        .iter()
+        .filter_map(|&number| {
+            payloads
+                .iter()
+                .map(|(_, payload)| payload)
+                .filter(|payload| {
+                    let flags = payload.stream_flags();
+                    flags.stream_number == number && flags.key_frame && payload.is_object_start()
+                })
+                .filter_map(|payload| payload.presentation_time())
+                .filter(|&key_frame| key_frame <= time)
+                .max()
+        })
+        .min()
+        .unwrap_or(time)
+}
+
+/// The number of the first packet with a payload presented at or after
+/// `time`.
+fn packet_at(packets: &[DataPacket], time: u32) -> usize {
+    packets
+        .iter()
+        .position(|packet| {
+            packet
+                .payload
+                .payloads()
+                .iter()
+                .any(|payload| payload.presentation_time() >= Some(time))
+        })
+        .unwrap_or(packets.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        header::{
+            marker::{Marker, MarkerData},
+            script_command::{Command, ScriptCommandData},
+        },
+        widestr::WideStr,
+    };
+
+    const BASIC_WMV: &[u8] = include_bytes!("../samples/basic.wmv");
+
+    /// Presentation times of the video key frames.
+    fn key_frames(container: &Container) -> Vec<u32> {
+        container
+            .data
+            .packets
+            .iter()
+            .flat_map(|packet| packet.payload.payloads())
+            .filter(|payload| {
+                let flags = payload.stream_flags();
+                flags.stream_number == 2 && flags.key_frame && payload.is_object_start()
+            })
+            .filter_map(|payload| payload.presentation_time())
+            .collect()
+    }
+
+    #[test]
+    fn trim_at_key_frame() {
+        let mut container = crate::parse(BASIC_WMV).unwrap();
+        let preroll = container.header.file_properties().unwrap().preroll as u32;
+        // Followed by over a second without key frames.
+        let key_frame = key_frames(&container)[2] - preroll;
+        let marker = |time: u32, description: &str| Marker {
+            offset: 0,
+            presentation_time: u64::from(time + preroll) * 10_000,
+            entry_length: 0,
+            send_time: time,
+            flags: 0,
+            marker_description: WideStr::new(description),
+        };
+        container
+            .header
+            .objects
+            .push(HeaderObject::Marker(MarkerData {
+                reserved_1: RESERVED_4,
+                reserved_2: 0,
+                name: WideStr::new(""),
+                markers: vec![marker(0, "before"), marker(key_frame + 500, "inside")],
+            }));
+        container
+            .header
+            .objects
+            .push(HeaderObject::ScriptCommand(ScriptCommandData {
+                reserved: RESERVED_3,
+                command_types: vec![WideStr::new("URL")],
+                commands: vec![Command {
+                    presentation_time: key_frame + preroll + 1000,
+                    type_index: 0,
+                    command_name: WideStr::new("https://example.com/"),
+                }],
+            }));
+        let duration = container.header.file_properties().unwrap().play_duration;
+
+        let container = trim(container, key_frame + 100, key_frame + 2000).expect("to trim");
+        let mut out = Vec::new();
+        container.write(&mut out).unwrap();
+        let container = crate::parse(&out).unwrap();
+
+        assert_eq!(key_frames(&container)[0], preroll);
+        let times = container
+            .data
+            .packets
+            .iter()
+            .flat_map(|packet| packet.payload.payloads())
+            .filter_map(|payload| payload.presentation_time());
+        assert!(times
+            .clone()
+            .all(|time| time >= preroll && time < preroll + 2000));
+        let props = container.header.file_properties().unwrap();
+        assert!(props.play_duration < duration);
+        assert_eq!(props.play_duration, u64::from(preroll + 2000) * 10_000);
+        assert_eq!(props.send_duration, 2000 * 10_000);
+        assert_eq!(
+            props.data_packets_count,
+            container.data.packets.len() as u64
+        );
+
+        for object in container.header.objects.iter() {
+            match object {
+                HeaderObject::Marker(data) => {
+                    assert_eq!(data.markers.len(), 1);
+                    assert_eq!(data.markers[0].send_time, 500);
+                    assert_eq!(
+                        data.markers[0].presentation_time,
+                        u64::from(preroll + 500) * 10_000
+                    );
+                }
+                HeaderObject::ScriptCommand(data) => {
+                    assert_eq!(data.commands[0].presentation_time, preroll + 1000)
+                }
+                _ => {}
+            }
+        }
+
+        assert!(trim(container, 1000, 1000).is_err());
+    }
+}
@@ -1,7 +1,6 @@
-use crate::span::Span;
+use crate::{error::ParseError, span::Span};
 use nom::{
     combinator::{complete, eof, map},
-    error::ParseError,
     multi::{length_count, many0},
     number::streaming::{le_u16, le_u32},
     sequence::terminated,